@@ -1,205 +1,3914 @@
-use eframe::{
-    egui::{self, Color32, Pos2, Shape, Vec2},
+use std::sync::Arc;
+
+use eframe::egui::{self, Color32, Pos2, Shape};
+use egui::{
+    epaint::{Mesh, PathStroke},
+    Stroke,
 };
-use egui::epaint::{PathStroke, PathShape};
-use egui::Stroke;
 
-use nalgebra::{Matrix4, Vector3};
+use nalgebra::{Matrix4, UnitQuaternion, Vector3, Vector4};
+use serde::{Deserialize, Serialize};
+
+mod mesh_io;
+mod raster;
+mod scene;
+use mesh_io::MeshWatcher;
+use raster::ZBuffer;
+use scene::{BooleanOp, BuiltGeometry, ImportedMesh, ObjectState, Primitive, SceneObject};
+
+/// Distance of the near clipping plane in camera space.
+/// Anything closer to the camera than this is clipped away.
+const NEAR_Z: f32 = 0.1;
+
+/// Initial camera pose, also restored by "Reset view". The initial
+/// orientation is the identity, looking at the model from +Z.
+const DEFAULT_DIST: f32 = 3.0;
+
+/// Keyboard nudge steps: radians per arrow press, and the zoom factor per
+/// PageUp/PageDown press. The `_LARGE` variants apply while Shift is held.
+const NUDGE_ANGLE: f32 = 5.0 * std::f32::consts::PI / 180.0;
+const NUDGE_ANGLE_LARGE: f32 = 15.0 * std::f32::consts::PI / 180.0;
+const NUDGE_ZOOM: f32 = 1.1;
+const NUDGE_ZOOM_LARGE: f32 = 1.5;
+
+/// Below this angular speed (radians per second) coasting stops.
+const INERTIA_MIN_SPEED: f32 = 0.01;
+
+/// How far past a level-of-detail distance the camera has to go before the
+/// level changes, as a fraction of the distance.
+const LOD_HYSTERESIS: f32 = 0.1;
+
+/// Frame times kept for the performance overlay's graph.
+const FRAME_HISTORY: usize = 120;
+
+/// Seconds a toast stays up, the last of them fading out.
+const TOAST_SECONDS: f64 = 2.0;
+
+/// How soon to look again while the 3D view has no room to draw in.
+const EMPTY_VIEW_RETRY: std::time::Duration = std::time::Duration::from_millis(100);
+
+/// Colors handed out to new scene objects, in order.
+const OBJECT_COLORS: [Color32; 6] = [
+    Color32::from_rgb(50, 100, 255),
+    Color32::from_rgb(255, 140, 50),
+    Color32::from_rgb(80, 200, 120),
+    Color32::from_rgb(220, 80, 160),
+    Color32::from_rgb(240, 210, 60),
+    Color32::from_rgb(90, 210, 230),
+];
+
+/// How long "Reset view" and the preset views take to glide to their pose.
+const CAMERA_TRANSITION_SECONDS: f32 = 0.4;
+
+/// Default distance of the far clipping plane in camera space.
+const DEFAULT_FAR_Z: f32 = 100.0;
+
+/// Length of the world-space axis lines, in world units.
+const AXES_LENGTH: f32 = 1.5;
+
+/// World axes with their overlay colors and labels, shared by the axes
+/// overlay and the translate gizmo.
+const AXES: [([f32; 3], Color32, &str); 3] = [
+    ([1.0, 0.0, 0.0], Color32::from_rgb(230, 60, 60), "X"),
+    ([0.0, 1.0, 0.0], Color32::from_rgb(60, 200, 60), "Y"),
+    ([0.0, 0.0, 1.0], Color32::from_rgb(70, 110, 255), "Z"),
+];
+
+/// Translate gizmo arm length, as a fraction of the camera distance so it
+/// keeps roughly the same size on screen. Handles grab within `GIZMO_GRAB_RADIUS` points.
+const GIZMO_SCALE: f32 = 0.2;
+const GIZMO_GRAB_RADIUS: f32 = 8.0;
+
+/// Initial vertical field of view of the perspective projection, in degrees.
+const DEFAULT_FOV_DEGREES: f32 = 45.0;
+
+/// Depths closer together than this are treated as equal when sorting, so
+/// near-coincident triangles keep their original order instead of swapping.
+const DEPTH_SORT_EPSILON: f32 = 1e-4;
+
+/// How the projected triangles get onto the screen.
+#[derive(Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+enum RenderMode {
+    /// Sort back-to-front and paint each triangle as an egui shape. This
+    /// also blends translucent objects in order.
+    Painter,
+    /// Rasterize into an offscreen depth buffer and blit it as a texture.
+    ZBuffer,
+    /// Only the triangle edges, no fill.
+    Wireframe,
+    /// Only the edges that aren't hidden behind other geometry. Uses the
+    /// z-buffer: faces are filled with depth only, then edges are
+    /// depth-tested against them.
+    HiddenLine,
+    /// A dot at every unique vertex. Quick structural preview of dense meshes.
+    Points,
+}
+
+/// Camera projection type.
+#[derive(Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+enum Projection {
+    Perspective,
+    /// Parallel projection: sizes don't change with depth, which makes
+    /// dimensions comparable. The scale follows `dist`, so zooming still works.
+    Orthographic,
+}
+
+/// What dragging and the movement keys do to the camera.
+#[derive(Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+enum Navigation {
+    /// Turn around the pivot.
+    Orbit,
+    /// First person: dragging looks around from where the camera is, W/A/S/D
+    /// move it along the view and sideways, and Q/E down and up.
+    Fly,
+}
+
+/// Radians the fly-mode view turns per point dragged, at a rotate speed of 1.
+const FLY_LOOK_SPEED: f32 = 0.005;
+
+/// How lighting is evaluated across a triangle.
+#[derive(Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+enum ShadingMode {
+    /// One shade per triangle, from its face normal.
+    Flat,
+    /// Gouraud: shade each corner from its vertex normal and interpolate.
+    Smooth,
+}
+
+/// Where each triangle's base color comes from.
+#[derive(Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+enum ColorMode {
+    /// The color of the triangle's object, lit.
+    Object,
+    /// A stable pseudo-random color per source triangle, lit. Handy for
+    /// spotting individual triangles and triangulation problems.
+    RandomPerFace,
+    /// Unlit gradient by camera depth, from blue (nearest visible) to red
+    /// (farthest visible). Shows clipping and sorting problems at a glance.
+    DepthHeatmap,
+}
+
+/// The egui theme, and the viewport colors that go with it.
+#[derive(Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+enum Theme {
+    Dark,
+    Light,
+}
+
+impl Theme {
+    fn visuals(self) -> egui::Visuals {
+        match self {
+            Theme::Dark => egui::Visuals::dark(),
+            Theme::Light => egui::Visuals::light(),
+        }
+    }
+
+    /// Default background gradient, top then bottom.
+    fn background(self) -> (Color32, Color32) {
+        match self {
+            Theme::Dark => (Color32::from_rgb(24, 26, 32), Color32::from_rgb(62, 66, 78)),
+            Theme::Light => (Color32::from_rgb(236, 238, 242), Color32::from_rgb(186, 192, 204)),
+        }
+    }
+
+    /// Default edge and point color, contrasting with the background.
+    fn wire_color(self) -> Color32 {
+        match self {
+            Theme::Dark => Color32::from_rgb(220, 220, 220),
+            Theme::Light => Color32::from_rgb(40, 40, 40),
+        }
+    }
+}
+
+/// Which triangles to skip based on their on-screen winding.
+#[derive(Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+enum CullMode {
+    None,
+    /// Skip triangles facing away from the camera (the usual choice).
+    Back,
+    /// Skip triangles facing the camera, for meshes with inverted winding.
+    Front,
+}
+
+/// Whether `RenderMode::Painter` outlines every triangle.
+#[derive(Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+enum EdgeStrokes {
+    /// Outline models of up to `AUTO_EDGE_LIMIT` triangles; on denser ones
+    /// the edges would cover the fills.
+    Auto,
+    On,
+    Off,
+}
+
+/// The most triangles `EdgeStrokes::Auto` still outlines.
+const AUTO_EDGE_LIMIT: usize = 20_000;
+
+/// Painter-mode edges of triangles smaller than this on screen, in square
+/// points, fade out in proportion, so tiny faces don't turn solid white.
+const EDGE_FADE_AREA: f32 = 64.0;
+
+/// One triangle of the model, in world space.
+#[derive(Clone, Copy)]
+struct Triangle {
+    /// Corner positions, counter-clockwise seen from outside.
+    pos: [[f32; 3]; 3],
+    /// Per-corner vertex normals, as produced by csgrs.
+    normal: [[f32; 3]; 3],
+    /// Unit face normal from the winding (zero if degenerate), worked out
+    /// once here rather than every frame.
+    face: Vector3<f32>,
+    /// Index into `MyApp::objects`.
+    object: usize,
+}
+
+impl Triangle {
+    fn new(pos: [[f32; 3]; 3], normal: [[f32; 3]; 3], object: usize) -> Self {
+        let face = face_normal(pos[0], pos[1], pos[2]);
+        Self { pos, normal, face, object }
+    }
+}
+
+/// A polygon corner carried through clipping, with the attributes that get
+/// interpolated along clipped edges.
+#[derive(Clone, Copy)]
+struct ClipVertex {
+    /// Camera-space position.
+    pos: Vector3<f32>,
+    shade: f32,
+    specular: f32,
+}
+
+impl ClipVertex {
+    fn lerp(&self, other: &ClipVertex, t: f32) -> ClipVertex {
+        ClipVertex {
+            pos: self.pos + (other.pos - self.pos) * t,
+            shade: self.shade + (other.shade - self.shade) * t,
+            specular: self.specular + (other.specular - self.specular) * t,
+        }
+    }
+}
+
+/// Which sections of the settings panel are expanded.
+#[derive(Clone, Copy, Serialize, Deserialize)]
+struct PanelSections {
+    objects: bool,
+    geometry: bool,
+    rendering: bool,
+    camera: bool,
+    measure: bool,
+    lighting: bool,
+    debug: bool,
+}
+
+/// The user's preferences as kept between runs, each a copy of the `MyApp`
+/// field of the same name. The scene and camera are stored separately, as
+/// a scene file; see `MyApp::save`.
+#[derive(Serialize, Deserialize)]
+struct Settings {
+    uniform_scale: bool,
+    show_gizmo: bool,
+    history_limit: usize,
+    import_replaces: bool,
+    screenshot_scale: u32,
+    sphere_lod: bool,
+    lod_distances: [f32; 2],
+    min_dist: f32,
+    max_dist: f32,
+    pan_with_secondary: bool,
+    navigation: Navigation,
+    fly_speed: f32,
+    rotate_sensitivity: f32,
+    invert_y: bool,
+    pan_sensitivity: f32,
+    inertia: bool,
+    inertia_damping: f32,
+    auto_rotate_speed: f32,
+    sections: PanelSections,
+    projection: Projection,
+    fov_degrees: f32,
+    far: f32,
+    render_mode: RenderMode,
+    theme: Theme,
+    bg_color: Color32,
+    bg_gradient: bool,
+    bg_color_bottom: Color32,
+    fog_enabled: bool,
+    fog_start: f32,
+    fog_end: f32,
+    wire_color: Color32,
+    wire_width: f32,
+    supersample: usize,
+    edge_strokes: EdgeStrokes,
+    edge_width: f32,
+    point_radius: f32,
+    cull_mode: CullMode,
+    shading: ShadingMode,
+    show_normals: bool,
+    highlight_hover: bool,
+    normal_length: f32,
+    show_axes: bool,
+    axes_gizmo: bool,
+    show_grid: bool,
+    grid_spacing: f32,
+    grid_extent: f32,
+    show_shadow: bool,
+    show_bbox: bool,
+    show_fps: bool,
+    measure_snap: bool,
+    color_mode: ColorMode,
+    light_dir: Vector3<f32>,
+    light_intensity: f32,
+    headlight: bool,
+    ambient: f32,
+    diffuse: f32,
+    specular: f32,
+    shininess: f32,
+}
+
+/// eframe storage keys.
+const SETTINGS_KEY: &str = "settings";
+const SCENE_KEY: &str = "scene";
+
+/// The editable state of the whole scene, as kept in the undo history.
+#[derive(Clone)]
+struct SceneSnapshot {
+    objects: Vec<ObjectState>,
+    selected: Option<usize>,
+}
+
+/// Current version of the `.json` scene format, stored in every file.
+const SCENE_FILE_VERSION: u64 = 1;
+
+/// Contents of a saved scene. Bump `SCENE_FILE_VERSION` on incompatible
+/// changes, and teach `MyApp::load_scene` to migrate the older layout.
+#[derive(Serialize, Deserialize)]
+struct SceneFile {
+    version: u64,
+    objects: Vec<ObjectState>,
+    camera: CameraPose,
+}
+
+/// Everything that places the camera, so it can be animated as a whole.
+#[derive(Clone, Copy, Serialize, Deserialize)]
+struct CameraPose {
+    orientation: UnitQuaternion<f32>,
+    pivot: Vector3<f32>,
+    dist: f32,
+}
+
+impl CameraPose {
+    /// The pose the app starts in and Home goes back to: looking at the
+    /// origin from `DEFAULT_DIST` along +Z.
+    fn home() -> CameraPose {
+        CameraPose {
+            orientation: UnitQuaternion::identity(),
+            pivot: Vector3::zeros(),
+            dist: DEFAULT_DIST,
+        }
+    }
+
+    fn lerp(&self, other: &CameraPose, t: f32) -> CameraPose {
+        let mix = |a: f32, b: f32| a + (b - a) * t;
+        CameraPose {
+            // Opposite quaternions are the same rotation; slerp can't pick a path between them
+            orientation: self.orientation.try_slerp(&other.orientation, t, 1e-6).unwrap_or(other.orientation),
+            pivot: self.pivot.lerp(&other.pivot, t),
+            dist: mix(self.dist, other.dist),
+        }
+    }
+}
+
+/// A viewpoint as copied to the clipboard: the pose plus the field of view.
+#[derive(Serialize, Deserialize)]
+struct CameraSnippet {
+    #[serde(flatten)]
+    pose: CameraPose,
+    fov_degrees: f32,
+}
+
+/// An in-flight camera animation toward `to`.
+struct CameraTransition {
+    from: CameraPose,
+    to: CameraPose,
+    elapsed: f32,
+}
+
+/// The six canonical axis-aligned views.
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum PresetView {
+    Front,
+    Back,
+    Left,
+    Right,
+    Top,
+    Bottom,
+}
+
+impl PresetView {
+    const ALL: [PresetView; 6] = [
+        PresetView::Front,
+        PresetView::Back,
+        PresetView::Left,
+        PresetView::Right,
+        PresetView::Top,
+        PresetView::Bottom,
+    ];
+
+    fn label(self) -> &'static str {
+        match self {
+            PresetView::Front => "Front",
+            PresetView::Back => "Back",
+            PresetView::Left => "Left",
+            PresetView::Right => "Right",
+            PresetView::Top => "Top",
+            PresetView::Bottom => "Bottom",
+        }
+    }
+
+    /// Camera orientation that looks at the model from the matching side.
+    /// Front looks from +Z (the default pose), right from +X, top from +Y.
+    fn orientation(self) -> UnitQuaternion<f32> {
+        use std::f32::consts::{FRAC_PI_2, PI};
+        let (yaw, pitch) = match self {
+            PresetView::Front => (0.0, 0.0),
+            PresetView::Back => (PI, 0.0),
+            PresetView::Left => (FRAC_PI_2, 0.0),
+            PresetView::Right => (-FRAC_PI_2, 0.0),
+            PresetView::Top => (0.0, FRAC_PI_2),
+            PresetView::Bottom => (0.0, -FRAC_PI_2),
+        };
+        orientation_from_yaw_pitch(yaw, pitch)
+    }
+}
+
+/// A triangle ready to be drawn: projected corners plus their camera-space depths.
+#[derive(Clone, Copy)]
+struct RenderTri {
+    /// Camera-space depth of each corner.
+    z: [f32; 3],
+    /// Lighting factor of each corner, in `[0, 1]`.
+    shade: [f32; 3],
+    /// Specular highlight of each corner, added on top of the shaded color.
+    specular: [f32; 3],
+    /// Index into `MyApp::objects`.
+    object: usize,
+    /// Index of the source triangle in `MyApp::triangles`.
+    source: usize,
+    /// Corners in normalized device coordinates (Y pointing down).
+    pts: [[f32; 2]; 3],
+}
+
+/// How many source triangles `MyApp::render_tris` dropped or cut, and why.
+#[derive(Clone, Copy, Default)]
+struct RenderStats {
+    /// Entirely outside the view.
+    outside: usize,
+    /// Crossing the near plane, so only partly drawn.
+    near_clipped: usize,
+    /// Dropped because of NaN/inf coordinates (e.g. bad vertices).
+    non_finite: usize,
+}
+
+impl std::ops::Add for RenderStats {
+    type Output = RenderStats;
+
+    fn add(self, other: RenderStats) -> RenderStats {
+        RenderStats {
+            outside: self.outside + other.outside,
+            near_clipped: self.near_clipped + other.near_clipped,
+            non_finite: self.non_finite + other.non_finite,
+        }
+    }
+}
+
+/// Everything `MyApp::render_tris` depends on. While it stays the same, the
+/// last frame's triangles are drawn again instead of being recomputed.
+#[derive(Clone, Copy, PartialEq)]
+struct RenderKey {
+    /// `MyApp::geometry_epoch` when the triangles were made.
+    geometry: u64,
+    cam: Matrix4<f32>,
+    proj: Matrix4<f32>,
+    light: Vector3<f32>,
+    /// Intensity, ambient, diffuse, specular and shininess.
+    lighting: [f32; 5],
+    shading: ShadingMode,
+    cull_mode: CullMode,
+    points: bool,
+    section: Option<Vector4<f32>>,
+}
+
+impl RenderTri {
+    fn min_z(&self) -> f32 {
+        self.z[0].min(self.z[1]).min(self.z[2])
+    }
+
+    fn max_z(&self) -> f32 {
+        self.z[0].max(self.z[1]).max(self.z[2])
+    }
+
+    /// Final lit color of each corner for a surface of color `base`.
+    fn colors(&self, base: Color32) -> [Color32; 3] {
+        [0, 1, 2].map(|k| lit_color(base, self.shade[k], self.specular[k]))
+    }
+
+    /// Sort key for back-to-front drawing: farthest extent first, then
+    /// farthest near-extent. Depths are quantized so the key is a total order.
+    fn depth_key(&self) -> (i64, i64) {
+        let q = |z: f32| -(z / DEPTH_SORT_EPSILON).round() as i64;
+        (q(self.max_z()), q(self.min_z()))
+    }
+}
+
+/// A small struct holding your geometry plus camera controls.
+struct MyApp {
+    /// Triangles of all visible objects in world space, in `f32`.
+    /// We store them here so we don’t have to rebuild them every frame.
+    triangles: Vec<Triangle>,
+    /// The scene, indexed by `Triangle::object`.
+    objects: Vec<SceneObject>,
+    /// Object whose recipe and placement the side panel edits.
+    selected: Option<usize>,
+    /// Exploded view: each visible object is drawn moved away from the
+    /// middle of them all, by this times its own distance from there.
+    /// `explode_offsets` holds each object's move, as of the last rebuild.
+    explode: f32,
+    explode_offsets: Vec<Vector3<f32>>,
+    /// Edit the selected object's scale as one number instead of per axis.
+    uniform_scale: bool,
+    /// Draw a translate gizmo on the selected object; `gizmo_axis` is the
+    /// handle being dragged, if any.
+    show_gizmo: bool,
+    gizmo_axis: Option<usize>,
+
+    /// Undo/redo history of scene edits, oldest first. Edits are committed
+    /// once the pointer is released, so a whole slider or gizmo drag is one
+    /// step. `committed` is the scene as of the last commit.
+    undo_stack: Vec<SceneSnapshot>,
+    redo_stack: Vec<SceneSnapshot>,
+    committed: SceneSnapshot,
+    history_limit: usize,
+
+    /// Outcome of the last file operation, shown in the status bar.
+    status: String,
+    /// Imported meshes replace the whole scene instead of joining it.
+    import_replaces: bool,
+    /// Screenshot size relative to the on-screen view, and where a 1×
+    /// screenshot goes once eframe hands back the frame.
+    screenshot_scale: u32,
+    pending_screenshot: Option<std::path::PathBuf>,
+    /// Reloads imported meshes when their files change; `None` if the
+    /// platform couldn't provide a watcher.
+    watcher: Option<MeshWatcher>,
+    /// Short notice faded in over the view, and the `input.time` it appeared at.
+    toast: Option<(String, f64)>,
+    /// Set when an object's recipe, placement or visibility changes; the
+    /// scene is rebuilt once per frame at most, and only then. Camera changes
+    /// never touch this.
+    dirty: bool,
+    /// Where worker threads send finished object geometry, and the ticket
+    /// for the next build; see `SceneObject::start_build`.
+    build_sender: std::sync::mpsc::Sender<BuiltGeometry>,
+    build_receiver: std::sync::mpsc::Receiver<BuiltGeometry>,
+    next_ticket: u64,
+    /// Build spheres coarser as the camera backs away: level `i + 1` starts
+    /// at distance `lod_distances[i]`. `lod_level` is the level in use.
+    sphere_lod: bool,
+    lod_distances: [f32; 2],
+    lod_level: u32,
+    /// Deduplicated corners of `triangles`, drawn by `RenderMode::Points`,
+    /// and the corners of each triangle as indices into them. Shared corners
+    /// are only transformed once per frame.
+    points: Vec<[f32; 3]>,
+    indices: Vec<[u32; 3]>,
+    /// Axis-aligned bounds `(min, max)` of `triangles`, or `None` when empty.
+    bounds: Option<(Vector3<f32>, Vector3<f32>)>,
+    /// Bounding sphere `(center, radius)` of `triangles`, or `None` when empty.
+    sphere: Option<(Vector3<f32>, f32)>,
+
+    /// Camera orientation: the rotation from world space into camera space.
+    orientation: UnitQuaternion<f32>,
+    /// World-space point the camera orbits around and looks at.
+    pivot: Vector3<f32>,
+    /// Camera distance from the pivot, kept within `[min_dist, max_dist]` when zooming.
+    dist: f32,
+    min_dist: f32,
+    max_dist: f32,
+
+    /// Also pan with the right button. The middle button always pans; the
+    /// right one can clash with context menus on some platforms.
+    pan_with_secondary: bool,
+    /// Orbit or fly, and the flying speed in world units per second. Flying
+    /// moves the pivot along with the eye, so orbiting afterwards turns
+    /// around the point `dist` ahead.
+    navigation: Navigation,
+    fly_speed: f32,
+    /// Arcball rotation gain; 1.0 makes the surface follow the cursor exactly.
+    rotate_sensitivity: f32,
+    /// Flip the tilt direction of vertical drags.
+    invert_y: bool,
+    /// Pan speed relative to the default camera distance.
+    pan_sensitivity: f32,
+
+    /// Rotation inertia: camera-space angular velocity (axis times radians per
+    /// second), picked up while dragging and decaying by
+    /// `exp(-inertia_damping * t)` after release.
+    inertia: bool,
+    inertia_damping: f32,
+    angular_velocity: Vector3<f32>,
+
+    /// Set while the camera glides to a new pose; any camera input cancels it.
+    transition: Option<CameraTransition>,
+
+    /// Turntable mode: spin about the world Y axis at `auto_rotate_speed`
+    /// radians per second.
+    auto_rotate: bool,
+    auto_rotate_speed: f32,
+
+    /// Side panel sections the user expanded; all but Debug start open.
+    sections: PanelSections,
+
+    /// Screen rect of the 3D view on the last frame. Input is handled before
+    /// layout, so this is the best estimate of where the view is.
+    viewport: egui::Rect,
+
+    /// Set by the "Fit to view" button, handled once the viewport rect is known.
+    fit_requested: bool,
+
+    projection: Projection,
+    /// Vertical field of view, in degrees.
+    fov_degrees: f32,
+
+    /// Far clipping plane distance. Pull it in to slice through big models.
+    far: f32,
+
+    render_mode: RenderMode,
+    /// The mode the W key switches back to when leaving wireframe.
+    solid_render_mode: RenderMode,
+    theme: Theme,
+    /// Clear color of the viewport, or the top of the gradient.
+    bg_color: Color32,
+    /// Fade the background from `bg_color` at the top to `bg_color_bottom`.
+    bg_gradient: bool,
+    bg_color_bottom: Color32,
+    /// Exponential distance fog toward `bg_color`, between these camera depths.
+    fog_enabled: bool,
+    fog_start: f32,
+    fog_end: f32,
+    /// Edge color in `RenderMode::Wireframe` and `RenderMode::HiddenLine`, and
+    /// edge width in `RenderMode::Wireframe`. Also the dot color in `RenderMode::Points`
+    /// and the color of the edge strokes in `RenderMode::Painter`.
+    wire_color: Color32,
+    wire_width: f32,
+    /// Anti-aliasing for the software rasterizer: it draws at this many
+    /// times the resolution on each axis, then averages down.
+    supersample: usize,
+    /// Triangle outlines in `RenderMode::Painter`, and their width in points.
+    edge_strokes: EdgeStrokes,
+    edge_width: f32,
+    /// Dot radius in `RenderMode::Points`, in points.
+    point_radius: f32,
+    cull_mode: CullMode,
+    shading: ShadingMode,
+    /// Debug overlay: each face normal as a short line from the face centroid.
+    show_normals: bool,
+    /// Outline the triangle under the cursor and describe it in a tooltip.
+    highlight_hover: bool,
+    /// Length of the normal lines, in world units.
+    normal_length: f32,
+    /// Overlay the world X/Y/Z axes, either through the origin at world scale
+    /// or as a small fixed-size gizmo in a corner (`axes_gizmo`).
+    show_axes: bool,
+    axes_gizmo: bool,
+    /// Reference grid on the Y=0 ground plane, `grid_spacing` apart and
+    /// reaching `grid_extent` from the origin in X and Z.
+    show_grid: bool,
+    grid_spacing: f32,
+    grid_extent: f32,
+    /// With the grid, darken the ground where the model blocks the light,
+    /// by flattening it onto the ground along the light direction.
+    /// `shadow` holds the flattened world-space triangles for the geometry
+    /// epoch and light direction in `shadow_key`.
+    show_shadow: bool,
+    shadow: Vec<[Vector3<f32>; 3]>,
+    shadow_key: Option<(u64, Vector3<f32>)>,
+    /// Overlay the edges of the model's axis-aligned bounding box.
+    show_bbox: bool,
+    /// Section plane: hide the part of the model past `section_offset` along
+    /// the world axis `AXES[section_axis]`, or the part short of it if
+    /// `section_flip`. Triangles straddling the plane are cut along it.
+    section: bool,
+    section_axis: usize,
+    section_offset: f32,
+    section_flip: bool,
+    /// Overlay the frame rate, a graph of recent frame times and the
+    /// triangle counts in the top-right corner. `frame_times` holds the last
+    /// `FRAME_HISTORY` `stable_dt` samples, oldest first.
+    show_fps: bool,
+    frame_times: std::collections::VecDeque<f32>,
+    /// Measurement mode: clicks on the model place the ends of `measure`,
+    /// a third click starting over. With `measure_snap` they land on the
+    /// nearest corner of the clicked triangle.
+    measuring: bool,
+    measure: Vec<Vector3<f32>>,
+    measure_snap: bool,
+    color_mode: ColorMode,
+
+    /// World-space direction pointing *toward* the light. Need not be normalized.
+    light_dir: Vector3<f32>,
+    light_intensity: f32,
+    /// Attach the light to the camera instead of using `light_dir`.
+    headlight: bool,
+    /// Constant light added everywhere, so faces turned away from the light stay visible.
+    ambient: f32,
+    /// Weight of the Lambert (diffuse) term.
+    diffuse: f32,
+    /// Weight of the Phong specular highlight.
+    specular: f32,
+    /// Phong exponent: low is a broad, matte highlight, high is a tight, glossy one.
+    shininess: f32,
+
+    /// Offscreen buffers and the texture they're uploaded to, for `RenderMode::ZBuffer`.
+    zbuffer: ZBuffer,
+    zbuffer_texture: Option<egui::TextureHandle>,
+    /// Bumped whenever `triangles` is replaced.
+    geometry_epoch: u64,
+    /// The viewport's triangles from the last frame, and what they were made from.
+    render_cache: Option<(RenderKey, Vec<RenderTri>)>,
+    /// Where the model meets the section plane, from `section_loops`, and
+    /// the geometry epoch and plane it was traced for.
+    section_outline: Vec<Vec<Vector3<f32>>>,
+    section_outline_key: Option<(u64, Vector4<f32>)>,
+    /// How long the last `render_tris` call for the viewport took.
+    render_time: std::time::Duration,
+    /// What it dropped or cut.
+    render_stats: RenderStats,
+}
+
+impl MyApp {
+    /// Start with the settings, scene and camera saved in `storage` by the
+    /// last run. `mesh` replaces the scene as its only object; with neither
+    /// there's the demo model.
+    fn new(storage: Option<&dyn eframe::Storage>, mesh: Option<ImportedMesh>) -> Self {
+        let (build_sender, build_receiver) = std::sync::mpsc::channel();
+        let home = CameraPose::home();
+
+        let mut app = Self {
+            triangles: Vec::new(),
+            build_sender,
+            build_receiver,
+            next_ticket: 0,
+            sphere_lod: true,
+            lod_distances: [6.0, 12.0],
+            lod_level: 0,
+            points: Vec::new(),
+            indices: Vec::new(),
+            bounds: None,
+            sphere: None,
+            objects: Vec::new(),
+            selected: None,
+            explode: 0.0,
+            explode_offsets: Vec::new(),
+            uniform_scale: true,
+            show_gizmo: true,
+            gizmo_axis: None,
+            undo_stack: Vec::new(),
+            redo_stack: Vec::new(),
+            committed: SceneSnapshot { objects: Vec::new(), selected: None },
+            history_limit: 100,
+            status: String::new(),
+            import_replaces: false,
+            screenshot_scale: 1,
+            pending_screenshot: None,
+            watcher: None,
+            toast: None,
+            dirty: true,
+            orientation: home.orientation,
+            pivot: home.pivot,
+            dist: home.dist,
+            // Never zoom past the near plane, or the model's center gets clipped
+            min_dist: NEAR_Z * 2.0,
+            max_dist: 50.0,
+            pan_with_secondary: true,
+            navigation: Navigation::Orbit,
+            fly_speed: 2.0,
+            rotate_sensitivity: 1.0,
+            invert_y: false,
+            pan_sensitivity: 1.0,
+            inertia: true,
+            inertia_damping: 4.0,
+            angular_velocity: Vector3::zeros(),
+            transition: None,
+            auto_rotate: false,
+            auto_rotate_speed: 0.5,
+            sections: PanelSections {
+                objects: true,
+                geometry: true,
+                rendering: true,
+                camera: true,
+                measure: false,
+                lighting: true,
+                debug: false,
+            },
+            viewport: egui::Rect::NOTHING,
+            fit_requested: false,
+            projection: Projection::Perspective,
+            fov_degrees: DEFAULT_FOV_DEGREES,
+            far: DEFAULT_FAR_Z,
+            render_mode: RenderMode::Painter,
+            solid_render_mode: RenderMode::Painter,
+            theme: Theme::Dark,
+            bg_color: Theme::Dark.background().0,
+            bg_gradient: true,
+            bg_color_bottom: Theme::Dark.background().1,
+            fog_enabled: false,
+            fog_start: 2.0,
+            fog_end: 10.0,
+            wire_color: Theme::Dark.wire_color(),
+            wire_width: 1.0,
+            supersample: 2,
+            edge_strokes: EdgeStrokes::Auto,
+            edge_width: 1.0,
+            point_radius: 1.5,
+            cull_mode: CullMode::Back,
+            shading: ShadingMode::Flat,
+            show_normals: false,
+            highlight_hover: true,
+            normal_length: 0.1,
+            show_axes: true,
+            axes_gizmo: true,
+            show_grid: true,
+            grid_spacing: 0.5,
+            grid_extent: 5.0,
+            show_shadow: true,
+            shadow: Vec::new(),
+            shadow_key: None,
+            show_bbox: false,
+            section: false,
+            section_axis: 0,
+            section_offset: 0.0,
+            section_flip: false,
+            show_fps: false,
+            frame_times: std::collections::VecDeque::with_capacity(FRAME_HISTORY),
+            measuring: false,
+            measure: Vec::new(),
+            measure_snap: false,
+            color_mode: ColorMode::Object,
+            // Upper-right-front, as seen from the default camera
+            light_dir: Vector3::new(0.4, 0.8, 0.6),
+            light_intensity: 1.0,
+            headlight: false,
+            ambient: 0.2,
+            diffuse: 0.8,
+            specular: 0.5,
+            shininess: 32.0,
+            zbuffer: ZBuffer::new(0, 0),
+            zbuffer_texture: None,
+            geometry_epoch: 0,
+            render_cache: None,
+            section_outline: Vec::new(),
+            section_outline_key: None,
+            render_time: std::time::Duration::ZERO,
+            render_stats: RenderStats::default(),
+        };
+        if let Some(settings) = storage.and_then(|storage| eframe::get_value(storage, SETTINGS_KEY)) {
+            app.apply_settings(settings);
+        }
+        // A scene that no longer loads (say, an imported file was deleted) leaves the demo
+        let saved_scene = storage.and_then(|storage| storage.get_string(SCENE_KEY)).filter(|_| mesh.is_none());
+        let restored = saved_scene.is_some_and(|json| {
+            app.read_scene(&json).inspect_err(|err| eprintln!("Couldn't restore the last scene: {err}")).is_ok()
+        });
+        match mesh {
+            Some(mesh) => app.add_mesh(mesh),
+            None if restored => {}
+            None => {
+                let model =
+                    SceneObject::new("Model", OBJECT_COLORS[0], Primitive::Cube, BooleanOp::Union, Some(Primitive::Sphere));
+                app.objects.push(model);
+                app.selected = Some(0);
+            }
+        }
+        app.rebuild();
+        app.committed = app.snapshot();
+        app
+    }
+
+    fn snapshot(&self) -> SceneSnapshot {
+        SceneSnapshot {
+            objects: self.objects.iter().map(SceneObject::state).collect(),
+            selected: self.selected,
+        }
+    }
+
+    /// Put the scene back to `snapshot`, rebuilding only the objects whose recipe differs.
+    fn restore(&mut self, snapshot: SceneSnapshot) {
+        self.objects.truncate(snapshot.objects.len());
+        for (i, state) in snapshot.objects.iter().enumerate() {
+            match self.objects.get_mut(i) {
+                Some(object) => object.restore(state.clone()),
+                None => {
+                    let mut object = SceneObject::new(&state.name, state.color, state.a, state.op, state.b);
+                    object.restore(state.clone());
+                    self.objects.push(object);
+                }
+            }
+        }
+        self.selected = snapshot.selected.filter(|&i| i < self.objects.len());
+        self.committed = snapshot;
+        self.dirty = true;
+    }
+
+    /// Record the edits made since the last commit as one undo step, unless
+    /// there are none. Selection alone doesn't count as an edit.
+    fn commit_edits(&mut self) {
+        let now = self.snapshot();
+        if now.objects == self.committed.objects {
+            self.committed.selected = now.selected;
+            return;
+        }
+        self.undo_stack.push(std::mem::replace(&mut self.committed, now));
+        let excess = self.undo_stack.len().saturating_sub(self.history_limit);
+        self.undo_stack.drain(..excess);
+        self.redo_stack.clear();
+    }
+
+    /// Ask for a file name and write the visible objects there as binary STL.
+    fn export_stl(&mut self) {
+        self.export_mesh("STL", "stl", mesh_io::write_stl);
+    }
+
+    /// Ask for a file name and write the visible objects there as Wavefront OBJ.
+    fn export_obj(&mut self) {
+        self.export_mesh("Wavefront OBJ", "obj", mesh_io::write_obj);
+    }
+
+    fn export_mesh(&mut self, kind: &str, extension: &str, write: fn(&std::path::Path, &[Triangle]) -> std::io::Result<()>) {
+        let Some(path) = rfd::FileDialog::new()
+            .add_filter(kind, &[extension])
+            .set_file_name(format!("model.{extension}"))
+            .save_file()
+        else {
+            return;
+        };
+        self.status = match write(&path, &self.triangles) {
+            Ok(()) => format!("Exported {} triangles to {}", self.triangles.len(), path.display()),
+            Err(err) => format!("Couldn't export {}: {err}", path.display()),
+        };
+    }
+
+    /// Ask for an STL file and import it.
+    fn open_stl(&mut self) {
+        if let Some(path) = rfd::FileDialog::new().add_filter("STL", &["stl"]).pick_file() {
+            self.import_stl(&path);
+        }
+    }
+
+    /// Load an STL as a new object, centered on the origin, and fit the view to it.
+    fn import_stl(&mut self, path: &std::path::Path) {
+        let triangles = match mesh_io::read_stl(path) {
+            Ok(triangles) => triangles,
+            Err(err) => {
+                self.status = format!("Couldn't import {}: {err}", path.display());
+                return;
+            }
+        };
+        self.status = format!("Imported {} triangles from {}", triangles.len(), path.display());
+
+        if self.import_replaces {
+            self.objects.clear();
+        }
+        self.add_mesh(ImportedMesh { path: path.to_owned(), triangles });
+    }
+
+    /// Add `mesh` as a new, selected object, centered on the origin.
+    fn add_mesh(&mut self, mesh: ImportedMesh) {
+        let index = self.objects.len();
+        let name = mesh.path.file_stem().map_or("Mesh".into(), |s| s.to_string_lossy().into_owned());
+        let center = bounding_box(&mesh.triangles).map(|(lo, hi)| (lo + hi) * 0.5);
+        let mut object = SceneObject::from_mesh(name, OBJECT_COLORS[index % OBJECT_COLORS.len()], mesh);
+        // Keep the file's coordinates in the mesh; move the object instead
+        object.position = -center.unwrap_or_else(Vector3::zeros);
+        object.update_transform();
+        self.objects.push(object);
+        self.selected = Some(index);
+        self.dirty = true;
+        self.fit_requested = true;
+    }
+
+    /// Read the changed mesh file at `path` again, into every object made
+    /// from it. Unlike an import this keeps the camera and the objects'
+    /// placement, and it isn't an undo step: the history is updated to match.
+    fn reload_mesh(&mut self, path: &std::path::Path, time: f64) {
+        let triangles = match mesh_io::read_stl(path) {
+            Ok(triangles) => triangles,
+            Err(err) => {
+                self.status = format!("Couldn't reload {}: {err}", path.display());
+                return;
+            }
+        };
+        let mesh = Arc::new(ImportedMesh { path: path.to_owned(), triangles });
+        let from_path = |m: &Option<Arc<ImportedMesh>>| m.as_ref().is_some_and(|m| m.path == path);
+        for object in self.objects.iter_mut().filter(|o| from_path(&o.mesh)) {
+            object.mesh = Some(mesh.clone());
+            object.dirty = true;
+            self.dirty = true;
+        }
+        let history = self.undo_stack.iter_mut().chain(&mut self.redo_stack).chain([&mut self.committed]);
+        for state in history.flat_map(|snapshot| &mut snapshot.objects).filter(|o| from_path(&o.mesh)) {
+            state.mesh = Some(mesh.clone());
+        }
+
+        let name = path.file_name().map_or_else(|| path.display().to_string(), |n| n.to_string_lossy().into_owned());
+        self.status = format!("Reloaded {} triangles from {}", mesh.triangles.len(), path.display());
+        self.toast = Some((format!("Reloaded {name}"), time));
+    }
+
+    fn settings(&self) -> Settings {
+        Settings {
+            theme: self.theme,
+            uniform_scale: self.uniform_scale,
+            show_gizmo: self.show_gizmo,
+            history_limit: self.history_limit,
+            import_replaces: self.import_replaces,
+            screenshot_scale: self.screenshot_scale,
+            sphere_lod: self.sphere_lod,
+            lod_distances: self.lod_distances,
+            min_dist: self.min_dist,
+            max_dist: self.max_dist,
+            pan_with_secondary: self.pan_with_secondary,
+            navigation: self.navigation,
+            fly_speed: self.fly_speed,
+            rotate_sensitivity: self.rotate_sensitivity,
+            invert_y: self.invert_y,
+            pan_sensitivity: self.pan_sensitivity,
+            inertia: self.inertia,
+            inertia_damping: self.inertia_damping,
+            auto_rotate_speed: self.auto_rotate_speed,
+            sections: self.sections,
+            projection: self.projection,
+            fov_degrees: self.fov_degrees,
+            far: self.far,
+            render_mode: self.render_mode,
+            bg_color: self.bg_color,
+            bg_gradient: self.bg_gradient,
+            bg_color_bottom: self.bg_color_bottom,
+            fog_enabled: self.fog_enabled,
+            fog_start: self.fog_start,
+            fog_end: self.fog_end,
+            wire_color: self.wire_color,
+            wire_width: self.wire_width,
+            supersample: self.supersample,
+            edge_strokes: self.edge_strokes,
+            edge_width: self.edge_width,
+            point_radius: self.point_radius,
+            cull_mode: self.cull_mode,
+            shading: self.shading,
+            show_normals: self.show_normals,
+            highlight_hover: self.highlight_hover,
+            normal_length: self.normal_length,
+            show_axes: self.show_axes,
+            axes_gizmo: self.axes_gizmo,
+            show_grid: self.show_grid,
+            grid_spacing: self.grid_spacing,
+            grid_extent: self.grid_extent,
+            show_shadow: self.show_shadow,
+            show_bbox: self.show_bbox,
+            show_fps: self.show_fps,
+            measure_snap: self.measure_snap,
+            color_mode: self.color_mode,
+            light_dir: self.light_dir,
+            light_intensity: self.light_intensity,
+            headlight: self.headlight,
+            ambient: self.ambient,
+            diffuse: self.diffuse,
+            specular: self.specular,
+            shininess: self.shininess,
+        }
+    }
+
+    fn apply_settings(&mut self, settings: Settings) {
+        self.theme = settings.theme;
+        self.uniform_scale = settings.uniform_scale;
+        self.show_gizmo = settings.show_gizmo;
+        self.history_limit = settings.history_limit;
+        self.import_replaces = settings.import_replaces;
+        self.screenshot_scale = settings.screenshot_scale;
+        self.sphere_lod = settings.sphere_lod;
+        self.lod_distances = settings.lod_distances;
+        self.min_dist = settings.min_dist;
+        self.max_dist = settings.max_dist;
+        self.pan_with_secondary = settings.pan_with_secondary;
+        self.navigation = settings.navigation;
+        self.fly_speed = settings.fly_speed;
+        self.rotate_sensitivity = settings.rotate_sensitivity;
+        self.invert_y = settings.invert_y;
+        self.pan_sensitivity = settings.pan_sensitivity;
+        self.inertia = settings.inertia;
+        self.inertia_damping = settings.inertia_damping;
+        self.auto_rotate_speed = settings.auto_rotate_speed;
+        self.sections = settings.sections;
+        self.projection = settings.projection;
+        self.fov_degrees = settings.fov_degrees;
+        self.far = settings.far;
+        self.render_mode = settings.render_mode;
+        self.bg_color = settings.bg_color;
+        self.bg_gradient = settings.bg_gradient;
+        self.bg_color_bottom = settings.bg_color_bottom;
+        self.fog_enabled = settings.fog_enabled;
+        self.fog_start = settings.fog_start;
+        self.fog_end = settings.fog_end;
+        self.wire_color = settings.wire_color;
+        self.wire_width = settings.wire_width;
+        self.supersample = settings.supersample;
+        self.edge_strokes = settings.edge_strokes;
+        self.edge_width = settings.edge_width;
+        self.point_radius = settings.point_radius;
+        self.cull_mode = settings.cull_mode;
+        self.shading = settings.shading;
+        self.show_normals = settings.show_normals;
+        self.highlight_hover = settings.highlight_hover;
+        self.normal_length = settings.normal_length;
+        self.show_axes = settings.show_axes;
+        self.axes_gizmo = settings.axes_gizmo;
+        self.show_grid = settings.show_grid;
+        self.grid_spacing = settings.grid_spacing;
+        self.grid_extent = settings.grid_extent;
+        self.show_shadow = settings.show_shadow;
+        self.show_bbox = settings.show_bbox;
+        self.show_fps = settings.show_fps;
+        self.measure_snap = settings.measure_snap;
+        self.color_mode = settings.color_mode;
+        self.light_dir = settings.light_dir;
+        self.light_intensity = settings.light_intensity;
+        self.headlight = settings.headlight;
+        self.ambient = settings.ambient;
+        self.diffuse = settings.diffuse;
+        self.specular = settings.specular;
+        self.shininess = settings.shininess;
+    }
+
+    /// Switch the UI to `theme`. Viewport colors still at the old theme's
+    /// defaults follow along; ones the user picked stay.
+    fn set_theme(&mut self, ctx: &egui::Context, theme: Theme) {
+        if (self.bg_color, self.bg_color_bottom) == self.theme.background() {
+            (self.bg_color, self.bg_color_bottom) = theme.background();
+        }
+        if self.wire_color == self.theme.wire_color() {
+            self.wire_color = theme.wire_color();
+        }
+        self.theme = theme;
+        ctx.set_visuals(theme.visuals());
+    }
+
+    /// The scene and camera, as written to scene files.
+    fn scene_file(&self) -> SceneFile {
+        SceneFile {
+            version: SCENE_FILE_VERSION,
+            objects: self.snapshot().objects,
+            camera: self.pose(),
+        }
+    }
+
+    /// Ask for a file name and save the scene and camera there as JSON.
+    fn save_scene(&mut self) {
+        let Some(path) = rfd::FileDialog::new().add_filter("Scene", &["json"]).set_file_name("scene.json").save_file()
+        else {
+            return;
+        };
+        let written = serde_json::to_string_pretty(&self.scene_file())
+            .map_err(std::io::Error::from)
+            .and_then(|json| std::fs::write(&path, json));
+        self.status = match written {
+            Ok(()) => format!("Saved scene to {}", path.display()),
+            Err(err) => format!("Couldn't save {}: {err}", path.display()),
+        };
+    }
+
+    /// Ask for a scene file and replace the scene and camera with its contents.
+    fn open_scene(&mut self) {
+        let Some(path) = rfd::FileDialog::new().add_filter("Scene", &["json"]).pick_file() else {
+            return;
+        };
+        self.status = match self.load_scene(&path) {
+            Ok(()) => format!("Loaded scene from {}", path.display()),
+            Err(err) => format!("Couldn't load {}: {err}", path.display()),
+        };
+    }
+
+    fn load_scene(&mut self, path: &std::path::Path) -> Result<(), Box<dyn std::error::Error>> {
+        self.read_scene(&std::fs::read_to_string(path)?)
+    }
+
+    /// Replace the scene and camera with the ones in `json`, a scene file's contents.
+    fn read_scene(&mut self, json: &str) -> Result<(), Box<dyn std::error::Error>> {
+        let value: serde_json::Value = serde_json::from_str(json)?;
+        let version = value.get("version").and_then(serde_json::Value::as_u64).ok_or("not a scene file")?;
+        // Older versions get migrated to the current layout here, once there are any
+        if version != SCENE_FILE_VERSION {
+            return Err(format!("unsupported scene format version {version}").into());
+        }
+        let file: SceneFile = serde_json::from_value(value)?;
+
+        self.restore(SceneSnapshot { objects: file.objects, selected: None });
+        self.undo_stack.clear();
+        self.redo_stack.clear();
+        self.move_camera(file.camera, true);
+        Ok(())
+    }
+
+    fn undo(&mut self) {
+        if let Some(snapshot) = self.undo_stack.pop() {
+            self.redo_stack.push(self.snapshot());
+            self.restore(snapshot);
+        }
+    }
+
+    fn redo(&mut self) {
+        if let Some(snapshot) = self.redo_stack.pop() {
+            self.undo_stack.push(self.snapshot());
+            self.restore(snapshot);
+        }
+    }
+
+    /// Rebuild the objects whose recipe changed, gather every visible one
+    /// into `triangles`, and clear `dirty`.
+    fn rebuild(&mut self) {
+        self.dirty = false;
+
+        // 1) Build some geometry from csgrs, only where it's stale. An object
+        // still waiting on a build starts the next one once that's in.
+        for object in self.objects.iter_mut().filter(|o| o.dirty && o.pending.is_none()) {
+            object.start_build(self.next_ticket, &self.build_sender, self.lod_level);
+            self.next_ticket += 1;
+        }
+
+        // 2) Gather triangle list in f32, in world space, with the objects
+        // pushed apart from their common middle when exploded
+        let visible: Vec<usize> = (0..self.objects.len()).filter(|&i| self.objects[i].visible).collect();
+        let mut offsets = vec![Vector3::zeros(); self.objects.len()];
+        if self.explode > 0.0 && visible.len() > 1 {
+            let centers: Vec<Vector3<f32>> = visible
+                .iter()
+                .map(|&i| {
+                    let object = &self.objects[i];
+                    let (center, _) = bounding_sphere(&object.triangles);
+                    object.transform.transform_point(&center.into()).coords
+                })
+                .collect();
+            let middle = centers.iter().sum::<Vector3<f32>>() / centers.len() as f32;
+            for (&i, center) in visible.iter().zip(centers) {
+                offsets[i] = (center - middle) * self.explode;
+            }
+        }
+        let triangles = visible.into_iter().flat_map(|i| self.objects[i].world_triangles(i, offsets[i])).collect();
+        self.set_triangles(triangles);
+        self.explode_offsets = offsets;
+    }
+
+    /// Append a new single-primitive object, beside the others, and select it.
+    fn add_object(&mut self, primitive: Primitive) {
+        let index = self.objects.len();
+        let mut object = SceneObject::new(
+            format!("{} {}", primitive.label(), index + 1),
+            OBJECT_COLORS[index % OBJECT_COLORS.len()],
+            primitive,
+            BooleanOp::Union,
+            None,
+        );
+        object.position = Vector3::new(2.0 * index as f32, 0.0, 0.0);
+        object.update_transform();
+        self.objects.push(object);
+        self.selected = Some(index);
+        self.dirty = true;
+    }
+
+    /// Put the camera back to its initial pose.
+    fn reset_view(&mut self, snap: bool) {
+        self.move_camera(CameraPose::home(), snap);
+    }
+
+    fn pose(&self) -> CameraPose {
+        CameraPose {
+            orientation: self.orientation,
+            pivot: self.pivot,
+            dist: self.dist,
+        }
+    }
+
+    fn set_pose(&mut self, pose: CameraPose) {
+        self.orientation = pose.orientation;
+        self.pivot = pose.pivot;
+        self.dist = pose.dist;
+    }
+
+    /// Glide the camera to `target`, or jump there right away if `snap`.
+    fn move_camera(&mut self, target: CameraPose, snap: bool) {
+        self.angular_velocity = Vector3::zeros();
+        if snap {
+            self.transition = None;
+            self.set_pose(target);
+        } else {
+            self.transition = Some(CameraTransition {
+                from: self.pose(),
+                to: target,
+                elapsed: 0.0,
+            });
+        }
+    }
+
+    /// The frame rate overlay: current FPS and frame time, the triangle
+    /// counts, and a bar per recent frame, scaled so 30 FPS is full height.
+    fn paint_performance(&self, painter: &egui::Painter, rect: egui::Rect) {
+        let dt = self.frame_times.back().copied().unwrap_or(0.0);
+        let drawn = self.render_cache.as_ref().map_or(0, |(_, render_tris)| render_tris.len());
+        let text = format!(
+            "{:.0} FPS, {:.1} ms\n{} triangles, {drawn} drawn",
+            1.0 / dt.max(1e-4),
+            dt * 1000.0,
+            self.triangles.len()
+        );
+        let font = egui::FontId::monospace(11.0);
+        let galley = painter.layout_no_wrap(text, font, Color32::WHITE);
+
+        let graph_size = egui::vec2(FRAME_HISTORY as f32, 32.0);
+        let padding = 6.0;
+        let width = graph_size.x.max(galley.size().x) + 2.0 * padding;
+        let height = galley.size().y + graph_size.y + 3.0 * padding;
+        let panel = egui::Rect::from_min_size(rect.right_top() + egui::vec2(-width - 8.0, 8.0), egui::vec2(width, height));
+        painter.rect_filled(panel, 4.0, Color32::from_black_alpha(160));
+        let text_pos = panel.min + egui::vec2(padding, padding);
+        let graph_bottom = panel.bottom() - padding;
+        painter.galley(text_pos, galley, Color32::WHITE);
+
+        let full = 1.0 / 30.0;
+        for (i, &dt) in self.frame_times.iter().enumerate() {
+            let x = panel.left() + padding + i as f32 + 0.5;
+            let h = (dt / full).min(1.0) * graph_size.y;
+            // Green while at 60 FPS or better, then yellow, red below 30
+            let color = if dt <= 1.0 / 55.0 {
+                Color32::from_rgb(80, 200, 120)
+            } else if dt < full {
+                Color32::from_rgb(230, 200, 60)
+            } else {
+                Color32::from_rgb(230, 80, 60)
+            };
+            painter.line_segment([Pos2::new(x, graph_bottom), Pos2::new(x, graph_bottom - h)], Stroke::new(1.0, color));
+        }
+    }
+
+    /// Put the current viewpoint on the clipboard as JSON.
+    fn copy_camera(&mut self, ctx: &egui::Context) {
+        let snippet = CameraSnippet { pose: self.pose(), fov_degrees: self.fov_degrees };
+        match serde_json::to_string(&snippet) {
+            Ok(text) => {
+                ctx.copy_text(text);
+                self.status = "Copied the camera to the clipboard".into();
+            }
+            Err(err) => self.status = format!("Couldn't copy the camera: {err}"),
+        }
+    }
+
+    /// Glide to the viewpoint in `text`, if it's one `copy_camera` made.
+    /// Returns whether it was.
+    fn paste_camera(&mut self, text: &str, snap: bool) -> bool {
+        let Ok(snippet) = serde_json::from_str::<CameraSnippet>(text) else {
+            return false;
+        };
+        self.fov_degrees = snippet.fov_degrees.clamp(20.0, 90.0);
+        self.move_camera(snippet.pose, snap);
+        self.status = "Pasted the camera from the clipboard".into();
+        true
+    }
+
+    /// Projection matrix for the current settings and a viewport of `aspect`.
+    fn projection_matrix(&self, aspect: f32) -> Matrix4<f32> {
+        match self.projection {
+            Projection::Perspective => build_projection(self.fov_degrees.to_radians(), aspect, NEAR_Z, self.far),
+            Projection::Orthographic => {
+                // Match the perspective view's size at the orbit center
+                let half_height = self.dist * (self.fov_degrees.to_radians() * 0.5).tan();
+                build_orthographic(half_height, aspect, NEAR_Z, self.far)
+            }
+        }
+    }
+
+    /// Turn to one of the canonical views, keeping the zoom. Any pan is
+    /// undone: the view orbits the model's center again.
+    fn set_preset_view(&mut self, view: PresetView, snap: bool) {
+        let target = CameraPose {
+            orientation: view.orientation(),
+            pivot: self.model_center(),
+            dist: self.dist,
+        };
+        self.move_camera(target, snap);
+    }
+
+    /// The middle of the model's bounding sphere, or the home pivot when
+    /// there's no geometry.
+    fn model_center(&self) -> Vector3<f32> {
+        self.sphere.map_or_else(|| CameraPose::home().pivot, |(center, _)| center)
+    }
+
+    /// Orbit around the model's center, with `dist` set so the whole model
+    /// fits in `rect` at the current orientation and field of view. Leaves
+    /// the camera alone when there's no geometry to fit.
+    fn fit_to_view(&mut self, rect: egui::Rect) {
+        let Some((center, radius)) = self.sphere else {
+            return;
+        };
+        let radius = radius.max(1e-3);
+
+        // Back off until the sphere fits the narrower of the two FOVs
+        let aspect = viewport_aspect(rect);
+        let half_fov_y = self.fov_degrees.to_radians() * 0.5;
+        let half_fov_x = (half_fov_y.tan() * aspect).atan();
+        self.dist = radius / half_fov_y.min(half_fov_x).sin();
+        self.pivot = center;
+    }
+
+    /// The world-space move, in the plane through the orbit center facing
+    /// the camera, that looks like a move of `delta` points on screen in a
+    /// view drawn in `rect`.
+    fn screen_to_world(&self, rect: egui::Rect, delta: egui::Vec2) -> Vector3<f32> {
+        // Both projections show `dist * tan(fov / 2)` either side of the center there
+        let per_point = 2.0 * self.dist * (self.fov_degrees.to_radians() * 0.5).tan() / rect.height().max(1.0);
+        let to_world = self.orientation.inverse();
+        // Screen Y grows downward, camera-space Y upward
+        to_world * Vector3::new(delta.x, -delta.y, 0.0) * per_point
+    }
+
+    /// Screen position of world point `p` in a 3D view drawn in `rect`, or
+    /// `None` if it's outside the near/far range.
+    fn world_to_screen(&self, rect: egui::Rect, p: Vector3<f32>) -> Option<Pos2> {
+        let v = transform(build_camera(self.orientation, self.pivot, self.dist), p.into());
+        if !(NEAR_Z..=self.far).contains(&v.z) {
+            return None;
+        }
+        let q = project(self.projection_matrix(viewport_aspect(rect)), v);
+        Some(Pos2::new(
+            rect.center().x + q[0] * rect.width() * 0.5,
+            rect.center().y + q[1] * rect.height() * 0.5,
+        ))
+    }
+
+    /// Screen positions of the translate gizmo's origin and its three arm
+    /// tips, when it's shown and entirely in view. It follows the object
+    /// where the exploded view draws it.
+    fn gizmo_handles(&self, rect: egui::Rect) -> Option<(Pos2, [Pos2; 3])> {
+        let index = self.selected?;
+        let object = self.objects.get(index).filter(|o| self.show_gizmo && o.visible)?;
+        let position = object.position + self.explode_offsets.get(index).copied().unwrap_or_else(Vector3::zeros);
+        let origin = self.world_to_screen(rect, position)?;
+        let length = self.dist * GIZMO_SCALE;
+        let tip = |k: usize| self.world_to_screen(rect, position + Vector3::from(AXES[k].0) * length);
+        Some((origin, [tip(0)?, tip(1)?, tip(2)?]))
+    }
+
+    /// World-space points where the view ray through screen position `pos`
+    /// crosses the near and far planes, for a 3D view drawn in `rect`.
+    fn view_ray(&self, rect: egui::Rect, pos: Pos2) -> (Vector3<f32>, Vector3<f32>) {
+        // Undo `to_screen`, then the projection and the camera
+        let ndc_x = (pos.x - rect.center().x) / (rect.width() * 0.5).max(1.0);
+        let ndc_y = (pos.y - rect.center().y) / (rect.height() * 0.5).max(1.0);
+        let cam = build_camera(self.orientation, self.pivot, self.dist);
+        let proj = self.projection_matrix(viewport_aspect(rect));
+        let inv = (proj * cam).try_inverse().unwrap_or_else(Matrix4::identity);
+        (transform(inv, [ndc_x, ndc_y, -1.0]), transform(inv, [ndc_x, ndc_y, 1.0]))
+    }
+
+    /// The frontmost triangle under screen position `pos`, between the near
+    /// and far planes, as its index and the world-space hit point.
+    fn pick(&self, rect: egui::Rect, pos: Pos2) -> Option<(usize, Vector3<f32>)> {
+        let (near, far) = self.view_ray(rect, pos);
+        // With the ray running from plane to plane, hits past `t = 1` are beyond the far one
+        let (index, _, hit) = pick_triangle(near, far - near, &self.triangles).filter(|&(_, t, _)| t <= 1.0)?;
+        Some((index, hit))
+    }
+
+    /// Replace the model, refreshing everything cached from it.
+    fn set_triangles(&mut self, triangles: Vec<Triangle>) {
+        (self.points, self.indices) = index_vertices(&triangles);
+        self.bounds = bounding_box(&triangles);
+        self.sphere = self.bounds.map(|_| bounding_sphere(&triangles));
+        self.triangles = triangles;
+        self.geometry_epoch += 1;
+    }
+}
+
+impl MyApp {
+    /// Unit world-space direction toward the light. A headlight shines from
+    /// the eye along the view direction. Otherwise the sliders edit the raw
+    /// vector, so it's normalized here.
+    fn light(&self) -> Vector3<f32> {
+        if self.headlight {
+            -camera_forward(self.orientation)
+        } else {
+            self.light_dir.try_normalize(f32::EPSILON).unwrap_or_else(Vector3::z)
+        }
+    }
+
+    /// The inputs `render_tris` would use for a view the size of `rect`.
+    fn render_key(&self, rect: egui::Rect) -> RenderKey {
+        RenderKey {
+            geometry: self.geometry_epoch,
+            cam: build_camera(self.orientation, self.pivot, self.dist),
+            proj: self.projection_matrix(viewport_aspect(rect)),
+            light: self.light(),
+            lighting: [self.light_intensity, self.ambient, self.diffuse, self.specular, self.shininess],
+            shading: self.shading,
+            cull_mode: self.cull_mode,
+            points: self.render_mode == RenderMode::Points,
+            section: self.section_plane(),
+        }
+    }
+
+    /// The section plane in world space, when enabled, as `plane` where
+    /// `plane.dot(&[x, y, z, 1])` is negative on the hidden side.
+    fn section_plane(&self) -> Option<Vector4<f32>> {
+        let sign = if self.section_flip { 1.0 } else { -1.0 };
+        let mut plane = Vector4::new(0.0, 0.0, 0.0, -sign * self.section_offset);
+        plane[self.section_axis] = sign;
+        self.section.then_some(plane)
+    }
+
+    /// The viewport's triangles for a view the size of `rect`, with the key
+    /// they were made for. Last frame's are taken out of `render_cache` while
+    /// nothing they depend on changed; otherwise they're made afresh, updating
+    /// `render_time` and `render_stats`. Put them back once drawn.
+    fn take_render_tris(&mut self, rect: egui::Rect) -> (RenderKey, Vec<RenderTri>) {
+        let key = self.render_key(rect);
+        match self.render_cache.take() {
+            Some((cached, render_tris)) if cached == key => (key, render_tris),
+            _ => {
+                let start = std::time::Instant::now();
+                let (render_tris, stats) = self.render_tris(rect);
+                self.render_time = start.elapsed();
+                self.render_stats = stats;
+                (key, render_tris)
+            }
+        }
+    }
+
+    /// Light, clip, project and cull every triangle for a view the size of
+    /// `rect`, giving camera-space depths and normalized device coordinates.
+    /// Also returns how many triangles were skipped or cut along the way.
+    fn render_tris(&self, rect: egui::Rect) -> (Vec<RenderTri>, RenderStats) {
+        let cam = build_camera(self.orientation, self.pivot, self.dist);
+        let proj = self.projection_matrix(viewport_aspect(rect));
+        let far = self.far;
+        let frustum = frustum_planes(cam, proj, far);
+        let section = self.section_plane();
+        // The same plane for camera-space points, which `cam` made from world ones
+        let section_cam = section.map(|plane| cam.try_inverse().map_or(plane, |inv| inv.transpose() * plane));
+        // A cut-open model shows its inside, which is made of back faces
+        let cull_mode = if section.is_some() { CullMode::None } else { self.cull_mode };
+        let shading = self.shading;
+        let light = self.light();
+        let intensity = self.light_intensity;
+        let (ambient, diffuse) = (self.ambient, self.diffuse);
+        let (specular, shininess) = (self.specular, self.shininess);
+        // World-space eye position, for the specular view vector
+        let eye = cam.try_inverse().map_or_else(Vector3::zeros, |inv| transform(inv, [0.0; 3]));
+        // Points mode draws vertices only, so it doesn't need any triangles
+        let (source_tris, indices): (&[Triangle], &[[u32; 3]]) = if self.render_mode == RenderMode::Points {
+            (&[], &[])
+        } else {
+            (&self.triangles, &self.indices)
+        };
+
+        // Each shared corner in camera space, with a bit set for every
+        // frustum plane it's outside of, and `SECTION_BIT` if it's hidden by
+        // the section plane
+        let vertices: Vec<(Vector3<f32>, u8)> = if source_tris.is_empty() {
+            Vec::new()
+        } else {
+            self.points
+                .iter()
+                .map(|&p| {
+                    let h = Vector4::new(p[0], p[1], p[2], 1.0);
+                    let outside = (0..6).filter(|&i| frustum[i].dot(&h) < 0.0).fold(0u8, |bits, i| bits | 1 << i);
+                    let sectioned = section.is_some_and(|plane| plane.dot(&h) < 0.0);
+                    (transform(cam, p), if sectioned { outside | SECTION_BIT } else { outside })
+                })
+                .collect()
+        };
+
+        // Append the renderable pieces of one triangle to `out`, counting
+        // the ones that don't make it whole
+        type Acc = (Vec<RenderTri>, RenderStats);
+        let add = |(mut out, mut stats): Acc, (source, (tri, index)): (usize, (&Triangle, &[u32; 3]))| {
+            let [p0, p1, p2] = tri.pos;
+
+            // Skip the lighting and projection when every corner is on the
+            // outer side of the same frustum plane
+            let shared = index.map(|i| vertices[i as usize]);
+            let outside = shared[0].1 & shared[1].1 & shared[2].1;
+            if outside != 0 {
+                if outside & !SECTION_BIT != 0 {
+                    stats.outside += 1;
+                }
+                return (out, stats);
+            }
+            if shared.iter().any(|&(_, outside)| outside & NEAR_PLANE_BIT != 0) {
+                stats.near_clipped += 1;
+            }
+
+            // Ambient + Lambert + Phong at world point `p` with normal `n`.
+            // Returns the diffuse shade and the specular highlight separately.
+            let phong = |p: Vector3<f32>, n: Vector3<f32>| {
+                let n_dot_l = n.dot(&light);
+                let shade = (ambient + n_dot_l.max(0.0) * intensity * diffuse).clamp(0.0, 1.0);
+                if n_dot_l <= 0.0 {
+                    return (shade, 0.0); // no highlight on the unlit side
+                }
+                let view = (eye - p).try_normalize(f32::EPSILON).unwrap_or_else(Vector3::zeros);
+                let reflected = n * (2.0 * n_dot_l) - light;
+                let spec = reflected.dot(&view).max(0.0).powf(shininess) * intensity * specular;
+                (shade, spec.clamp(0.0, 1.0))
+            };
+            // Either once from the face normal at the centroid, or per corner
+            let lit = match shading {
+                ShadingMode::Flat => {
+                    let centroid = (Vector3::from(p0) + Vector3::from(p1) + Vector3::from(p2)) / 3.0;
+                    [phong(centroid, tri.face); 3]
+                }
+                ShadingMode::Smooth => {
+                    [0, 1, 2].map(|k| phong(Vector3::from(tri.pos[k]), Vector3::from(tri.normal[k])))
+                }
+            };
+
+            // Corners in camera space, shared with neighboring triangles
+            let corners = [0, 1, 2].map(|k| ClipVertex {
+                pos: shared[k].0,
+                shade: lit[k].0,
+                specular: lit[k].1,
+            });
+
+            // Clip against the near and far planes. A triangle straddling a
+            // plane becomes a smaller polygon; one fully outside vanishes.
+            let clipped = clip_polygon(&corners, |v| v.z - NEAR_Z);
+            let mut clipped = clip_polygon(&clipped, |v| far - v.z);
+            // The section plane cuts the same way, where it runs through the triangle
+            if let Some(plane) = section_cam.filter(|_| shared.iter().any(|&(_, bits)| bits & SECTION_BIT != 0)) {
+                clipped = clip_polygon(&clipped, |v| plane.xyz().dot(&v) + plane.w);
+            }
+
+            // Fan-triangulate the clipped (convex) polygon
+            for i in 1..clipped.len().saturating_sub(1) {
+                let (c0, c1, c2) = (clipped[0], clipped[i], clipped[i + 1]);
+                let (v0, v1, v2) = (c0.pos, c1.pos, c2.pos);
+                let shade = [c0.shade, c1.shade, c2.shade];
+                let specular = [c0.specular, c1.specular, c2.specular];
+
+                // We'll store the corner depths, plus the 3 points in 2D
+                let z = [v0.z, v1.z, v2.z];
+
+                let p0_2d = project(proj, v0);
+                let p1_2d = project(proj, v1);
+                let p2_2d = project(proj, v2);
+
+                // csgrs winds faces counter-clockwise seen from outside, which
+                // is a negative area in Y-down screen space.
+                let front_facing = signed_area_2d([p0_2d, p1_2d, p2_2d]) < 0.0;
+                match cull_mode {
+                    CullMode::Back if !front_facing => continue,
+                    CullMode::Front if front_facing => continue,
+                    _ => {}
+                }
+
+                // A non-finite depth would poison the sort below, so drop it
+                let finite = z.iter().all(|d| d.is_finite())
+                    && [p0_2d, p1_2d, p2_2d].iter().flatten().all(|c| c.is_finite());
+                if !finite {
+                    stats.non_finite += 1;
+                    continue;
+                }
+
+                out.push(RenderTri { z, shade, specular, object: tri.object, source, pts: [p0_2d, p1_2d, p2_2d] });
+            }
+            (out, stats)
+        };
+
+        // In parallel, each thread fills its own list; joining them in order
+        // keeps mesh order, which the depth sort relies on for ties
+        #[cfg(feature = "rayon")]
+        let (render_tris, stats) = {
+            use rayon::prelude::*;
+            source_tris.par_iter().zip(indices).enumerate().fold(|| (Vec::new(), RenderStats::default()), add).reduce(
+                || (Vec::new(), RenderStats::default()),
+                |(mut a, s), (b, t)| {
+                    a.extend(b);
+                    (a, s + t)
+                },
+            )
+        };
+        #[cfg(not(feature = "rayon"))]
+        let (render_tris, stats) =
+            source_tris.iter().zip(indices).enumerate().fold((Vec::new(), RenderStats::default()), add);
+
+        if stats.non_finite > 0 {
+            eprintln!("skipped {} triangle(s) with non-finite coordinates", stats.non_finite);
+        }
+
+        (render_tris, stats)
+    }
+
+    /// Render the model in software for a view the size of `rect`, at
+    /// `pixels_per_point` pixels per point, supersampled. Overlays aren't included.
+    fn render_image(&self, rect: egui::Rect, pixels_per_point: f32) -> egui::ColorImage {
+        let (render_tris, _) = self.render_tris(rect);
+        let depth_range = depth_range(&render_tris);
+        let samples = self.supersample;
+        let width = (rect.width() * pixels_per_point).round().max(1.0) as usize * samples;
+        let height = (rect.height() * pixels_per_point).round().max(1.0) as usize * samples;
+        let pixels_per_point = pixels_per_point * samples as f32;
+        let mut zbuffer = ZBuffer::new(width, height);
+        if self.bg_gradient {
+            zbuffer.fill_gradient(self.bg_color, self.bg_color_bottom);
+        } else {
+            zbuffer.clear(width, height, self.bg_color);
+        }
+
+        // Same mapping as the on-screen `to_screen`, relative to the view's corner
+        let (half_w, half_h) = (rect.width() * 0.5, rect.height() * 0.5);
+        let to_pixel = |p: [f32; 2]| {
+            [
+                (half_w + p[0] * half_w) * pixels_per_point,
+                (half_h + p[1] * half_h) * pixels_per_point,
+            ]
+        };
+        let edges = |zbuffer: &mut ZBuffer| {
+            for tri in &render_tris {
+                let pts = tri.pts.map(to_pixel);
+                for (i, j) in [(0, 1), (1, 2), (2, 0)] {
+                    zbuffer.draw_line(pts[i], pts[j], [tri.z[i], tri.z[j]], samples, self.wire_color);
+                }
+            }
+        };
+        match self.render_mode {
+            RenderMode::Painter | RenderMode::ZBuffer => self.fill_zbuffer(&mut zbuffer, &render_tris, to_pixel, depth_range),
+            RenderMode::HiddenLine => {
+                // Faces only occlude, leaving the background visible as on screen
+                for tri in &render_tris {
+                    zbuffer.fill_depth(tri.pts.map(to_pixel), tri.z);
+                }
+                edges(&mut zbuffer);
+            }
+            // Nothing fills the depth buffer, so every line passes the test
+            RenderMode::Wireframe => edges(&mut zbuffer),
+            RenderMode::Points => {
+                let cam = build_camera(self.orientation, self.pivot, self.dist);
+                let proj = self.projection_matrix(viewport_aspect(rect));
+                for &p in &self.points {
+                    let v = transform(cam, p);
+                    if (NEAR_Z..=self.far).contains(&v.z) {
+                        let c = to_pixel(project(proj, v));
+                        zbuffer.draw_line(c, c, [v.z; 2], samples, self.wire_color);
+                    }
+                }
+            }
+        }
+        zbuffer.to_image(samples)
+    }
+
+    /// Vector drawing of the model as seen in a view the size of `rect`.
+    /// Filled modes become back-to-front sorted polygons, so like the painter
+    /// path they can't untangle interpenetrating faces; hidden-line mode
+    /// fills the faces with the background color to hide edges behind them.
+    fn svg_drawing(&self, rect: egui::Rect) -> String {
+        use std::fmt::Write;
+
+        let (mut render_tris, _) = self.render_tris(rect);
+        let depth_range = depth_range(&render_tris);
+        let (half_w, half_h) = (rect.width() * 0.5, rect.height() * 0.5);
+        let to_svg = |p: [f32; 2]| (half_w + p[0] * half_w, half_h + p[1] * half_h);
+        let hex = |c: Color32| {
+            let [r, g, b, _] = c.to_srgba_unmultiplied();
+            format!("#{r:02x}{g:02x}{b:02x}")
+        };
+
+        let mut svg = String::new();
+        let _ = writeln!(
+            svg,
+            r#"<svg xmlns="http://www.w3.org/2000/svg" width="{w}" height="{h}" viewBox="0 0 {w} {h}">"#,
+            w = rect.width(),
+            h = rect.height()
+        );
+        let _ = writeln!(svg, r#"<rect width="100%" height="100%" fill="{}"/>"#, hex(self.bg_color));
+        let points = |pts: [[f32; 2]; 3]| pts.map(to_svg).map(|(x, y)| format!("{x:.2},{y:.2}")).join(" ");
+        match self.render_mode {
+            RenderMode::Painter | RenderMode::ZBuffer | RenderMode::HiddenLine => {
+                render_tris.sort_by_key(RenderTri::depth_key);
+                for tri in &render_tris {
+                    let (fill, stroke) = if self.render_mode == RenderMode::HiddenLine {
+                        (self.bg_color, self.wire_color)
+                    } else {
+                        // SVG polygons have one fill, so smooth shading averages the corners
+                        let [a, b, c] = self.tri_colors(tri, depth_range);
+                        let fill = a.lerp_to_gamma(b, 0.5).lerp_to_gamma(c, 1.0 / 3.0);
+                        (fill, fill)
+                    };
+                    let _ = writeln!(
+                        svg,
+                        r#"<polygon points="{}" fill="{}" stroke="{}" stroke-width="0.5" stroke-linejoin="round" opacity="{:.3}"/>"#,
+                        points(tri.pts),
+                        hex(fill),
+                        hex(stroke),
+                        fill.a() as f32 / 255.0
+                    );
+                }
+            }
+            RenderMode::Wireframe => {
+                for tri in &render_tris {
+                    for (i, j) in [(0, 1), (1, 2), (2, 0)] {
+                        let ((x1, y1), (x2, y2)) = (to_svg(tri.pts[i]), to_svg(tri.pts[j]));
+                        let _ = writeln!(
+                            svg,
+                            r#"<line x1="{x1:.2}" y1="{y1:.2}" x2="{x2:.2}" y2="{y2:.2}" stroke="{}" stroke-width="{}"/>"#,
+                            hex(self.wire_color),
+                            self.wire_width
+                        );
+                    }
+                }
+            }
+            RenderMode::Points => {
+                let cam = build_camera(self.orientation, self.pivot, self.dist);
+                let proj = self.projection_matrix(viewport_aspect(rect));
+                for &p in &self.points {
+                    let v = transform(cam, p);
+                    if (NEAR_Z..=self.far).contains(&v.z) {
+                        let (x, y) = to_svg(project(proj, v));
+                        let _ = writeln!(
+                            svg,
+                            r#"<circle cx="{x:.2}" cy="{y:.2}" r="{}" fill="{}"/>"#,
+                            self.point_radius,
+                            hex(self.wire_color)
+                        );
+                    }
+                }
+            }
+        }
+        svg.push_str("</svg>\n");
+        svg
+    }
+
+    /// Ask for a file name and write the current view there as SVG.
+    fn export_svg(&mut self) {
+        let Some(path) = rfd::FileDialog::new().add_filter("SVG", &["svg"]).set_file_name("view.svg").save_file() else {
+            return;
+        };
+        self.status = match std::fs::write(&path, self.svg_drawing(self.viewport)) {
+            Ok(()) => format!("Exported the view to {}", path.display()),
+            Err(err) => format!("Couldn't export {}: {err}", path.display()),
+        };
+    }
+
+    /// Ask for a PNG file name and save the view there. At 1× this reads
+    /// back the window, overlays and all, once the next frame is drawn;
+    /// larger scales re-render the model in software.
+    fn save_screenshot(&mut self, ctx: &egui::Context) {
+        let Some(path) = rfd::FileDialog::new().add_filter("PNG", &["png"]).set_file_name("screenshot.png").save_file()
+        else {
+            return;
+        };
+        if self.screenshot_scale == 1 {
+            self.pending_screenshot = Some(path);
+            ctx.send_viewport_cmd(egui::ViewportCommand::Screenshot(egui::UserData::default()));
+        } else {
+            let image = self.render_image(self.viewport, ctx.pixels_per_point() * self.screenshot_scale as f32);
+            self.write_screenshot(&path, &image);
+        }
+    }
+
+    fn write_screenshot(&mut self, path: &std::path::Path, image: &egui::ColorImage) {
+        let [width, height] = image.size;
+        self.status = match write_png(path, image) {
+            Ok(()) => format!("Saved {width}×{height} screenshot to {}", path.display()),
+            Err(err) => format!("Couldn't save {}: {err}", path.display()),
+        };
+    }
+
+    /// Final per-corner colors of a triangle under the current color mode.
+    /// `depth_range` is the `(min, max)` camera depth of everything visible.
+    fn tri_colors(&self, tri: &RenderTri, depth_range: (f32, f32)) -> [Color32; 3] {
+        let base = match self.color_mode {
+            ColorMode::Object => self.objects[tri.object].color,
+            ColorMode::RandomPerFace => random_color(tri.source),
+            ColorMode::DepthHeatmap => {
+                let (near, far) = depth_range;
+                let span = (far - near).max(f32::EPSILON);
+                return tri.z.map(|z| heatmap_color((z - near) / span));
+            }
+        };
+        let colors = tri.colors(base);
+        if !self.fog_enabled {
+            return colors;
+        }
+        // Colors are premultiplied; fade toward the background at the same opacity
+        [0, 1, 2].map(|k| {
+            let fog = self.bg_color.gamma_multiply(colors[k].a() as f32 / 255.0);
+            colors[k].lerp_to_gamma(fog, fog_amount(tri.z[k], self.fog_start, self.fog_end))
+        })
+    }
+
+    /// Rasterize `render_tris` into `zbuffer` in their colors, with
+    /// `to_pixel` placing them. Opaque triangles go first; translucent ones
+    /// are then blended over them from back to front, hidden by opaque ones
+    /// in front but not by each other. Like the painter's algorithm, that
+    /// order can't be right everywhere for translucent surfaces that
+    /// intersect or overlap cyclically.
+    fn fill_zbuffer(&self, zbuffer: &mut ZBuffer, render_tris: &[RenderTri], to_pixel: impl Fn([f32; 2]) -> [f32; 2], depth_range: (f32, f32)) {
+        let mut translucent = Vec::new();
+        for tri in render_tris {
+            let colors = self.tri_colors(tri, depth_range);
+            if colors.iter().all(|c| c.is_opaque()) {
+                zbuffer.fill_triangle(tri.pts.map(&to_pixel), tri.z, colors);
+            } else {
+                translucent.push((tri, colors));
+            }
+        }
+        translucent.sort_by_key(|(tri, _)| tri.depth_key());
+        for (tri, colors) in translucent {
+            zbuffer.blend_triangle(tri.pts.map(&to_pixel), tri.z, colors);
+        }
+    }
+}
+
+impl eframe::App for MyApp {
+    /// Keep the settings, scene and camera for the next run. Geometry isn't
+    /// stored; it's rebuilt from the objects' recipes and files.
+    fn save(&mut self, storage: &mut dyn eframe::Storage) {
+        eframe::set_value(storage, SETTINGS_KEY, &self.settings());
+        match serde_json::to_string(&self.scene_file()) {
+            Ok(json) => storage.set_string(SCENE_KEY, json),
+            Err(err) => eprintln!("Couldn't save the scene: {err}"),
+        }
+    }
+
+    fn update(&mut self, ctx: &egui::Context, _frame: &mut eframe::Frame) {
+        // Respond to mouse input for rotation, panning, zooming:
+        let input = ctx.input(|i| i.clone());
+        let dt = input.stable_dt.max(1e-4);
+        if self.frame_times.len() == FRAME_HISTORY {
+            self.frame_times.pop_front();
+        }
+        self.frame_times.push_back(input.stable_dt);
+
+        // Advance a camera glide, easing in and out
+        if let Some(transition) = &mut self.transition {
+            transition.elapsed += dt;
+            let t = (transition.elapsed / CAMERA_TRANSITION_SECONDS).min(1.0);
+            let pose = transition.from.lerp(&transition.to, t * t * (3.0 - 2.0 * t));
+            if t >= 1.0 {
+                self.transition = None;
+            } else {
+                ctx.request_repaint();
+            }
+            self.set_pose(pose);
+        }
+
+        // Double-click => orbit around the surface point under the cursor,
+        // and bring it to the middle of the view. Clicking empty space does nothing.
+        if input.pointer.button_double_clicked(egui::PointerButton::Primary) {
+            let pos = input.pointer.interact_pos().filter(|p| self.viewport.contains(*p));
+            if let Some((_, hit)) = pos.and_then(|pos| self.pick(self.viewport, pos)) {
+                // Slide the camera sideways only, so the hit point stays at its depth
+                self.dist += camera_forward(self.orientation).dot(&(hit - self.pivot));
+                self.dist = self.dist.clamp(self.min_dist, self.max_dist);
+                self.pivot = hit;
+                self.transition = None;
+            }
+        }
+
+        // A requested window capture arrives as an event; keep just the 3D view
+        for event in &input.raw.events {
+            if let egui::Event::Screenshot { image, .. } = event {
+                if let Some(path) = self.pending_screenshot.take() {
+                    self.write_screenshot(&path, &image.region(&self.viewport, Some(input.pixels_per_point)));
+                }
+            }
+        }
+
+        // Files dropped onto the window are imported
+        for file in &input.raw.dropped_files {
+            if let Some(path) = &file.path {
+                self.import_stl(path);
+            }
+        }
+
+        // Imported files rewritten by another program are read again
+        if let Some(watcher) = &mut self.watcher {
+            watcher.set_files(self.objects.iter().filter_map(|o| o.mesh.as_ref()).map(|m| m.path.as_path()));
+            for path in watcher.poll() {
+                self.reload_mesh(&path, input.time);
+            }
+        }
+
+        // Grabbing a gizmo handle moves the selected object instead of the camera
+        if input.pointer.primary_pressed() {
+            self.gizmo_axis = input.pointer.press_origin().and_then(|pos| {
+                let (origin, tips) = self.gizmo_handles(self.viewport)?;
+                (0..3)
+                    .map(|k| (k, distance_to_segment(pos, origin, tips[k])))
+                    .filter(|&(_, d)| d <= GIZMO_GRAB_RADIUS)
+                    .min_by(|a, b| a.1.total_cmp(&b.1))
+                    .map(|(k, _)| k)
+            });
+        }
+        if !input.pointer.primary_down() {
+            self.gizmo_axis = None;
+        }
+
+        let mut rotating = false;
+        if let Some(axis) = self.gizmo_axis {
+            // Slide along the axis by how far the pointer moved along its
+            // on-screen direction, measured against the arm's screen length
+            if let Some((origin, tips)) = self.gizmo_handles(self.viewport) {
+                let arm = tips[axis] - origin;
+                let arm_len2 = arm.length_sq();
+                if arm_len2 > 1.0 {
+                    let along = input.pointer.delta().dot(arm) / arm_len2 * self.dist * GIZMO_SCALE;
+                    if let Some(object) = self.selected.and_then(|i| self.objects.get_mut(i)) {
+                        object.position[axis] += along;
+                        object.update_transform();
+                        self.dirty = true;
+                    }
+                }
+            }
+            self.angular_velocity = Vector3::zeros();
+            rotating = true;
+        } else if input.pointer.is_decidedly_dragging()
+            // Only drags on the 3D view itself move the camera, not ones on
+            // panel widgets or popups, even where those overlap it
+            && ctx.is_being_dragged(viewport_id())
+        {
+            self.transition = None; // the user takes over
+            let drag_delta = input.pointer.delta();
+            // Left click => rotate
+            // Middle click (or right click, if enabled) => pan
+            let pan_down = input.pointer.button_down(egui::PointerButton::Middle)
+                || (self.pan_with_secondary && input.pointer.button_down(egui::PointerButton::Secondary));
+            if input.pointer.button_down(egui::PointerButton::Primary) && self.navigation == Navigation::Fly {
+                // Look around: turn about the world up axis for horizontal
+                // drags and the camera's sideways axis for vertical ones,
+                // keeping the eye in place
+                let drag = if self.invert_y { egui::vec2(drag_delta.x, -drag_delta.y) } else { drag_delta };
+                let turn = drag * FLY_LOOK_SPEED * self.rotate_sensitivity;
+                let eye = self.pivot - camera_forward(self.orientation) * self.dist;
+                self.orientation = UnitQuaternion::from_axis_angle(&Vector3::x_axis(), turn.y)
+                    * self.orientation
+                    * UnitQuaternion::from_axis_angle(&Vector3::y_axis(), turn.x);
+                self.pivot = eye + camera_forward(self.orientation) * self.dist;
+                self.angular_velocity = Vector3::zeros();
+                rotating = true;
+            } else if input.pointer.button_down(egui::PointerButton::Primary) {
+                // Arcball: turn the sphere under the cursor from where the pointer
+                // was to where it is now. This works in positions, not per-frame
+                // steps, so it's independent of the frame rate.
+                if let Some(cur) = input.pointer.latest_pos() {
+                    let drag = if self.invert_y { egui::vec2(drag_delta.x, -drag_delta.y) } else { drag_delta };
+                    let from = arcball_point(self.viewport, cur - drag);
+                    let to = arcball_point(self.viewport, cur);
+                    let step = UnitQuaternion::rotation_between(&from, &to)
+                        .map_or_else(Vector3::zeros, |q| q.scaled_axis())
+                        * self.rotate_sensitivity;
+                    self.orientation = UnitQuaternion::from_scaled_axis(step) * self.orientation;
+
+                    // Remember how fast we're turning, to coast after release
+                    self.angular_velocity = step / dt;
+                }
+                rotating = true;
+            } else if pan_down {
+                // Slide the orbit center against the drag, so at full speed
+                // whatever was grabbed at its depth stays under the cursor
+                self.pivot -= self.screen_to_world(self.viewport, drag_delta) * self.pan_sensitivity;
+            }
+        }
+        // Keep spinning after a flick, slowing down exponentially
+        if !rotating && self.inertia {
+            if self.angular_velocity.norm() > INERTIA_MIN_SPEED {
+                self.orientation = UnitQuaternion::from_scaled_axis(self.angular_velocity * dt) * self.orientation;
+                self.angular_velocity *= (-self.inertia_damping * dt).exp();
+                ctx.request_repaint();
+            } else {
+                self.angular_velocity = Vector3::zeros();
+            }
+        }
+
+        // Turntable: spin at a constant rate, animating even without input
+        if self.auto_rotate && !rotating {
+            // World-space rotation, so it's applied before the view rotation
+            self.orientation *= UnitQuaternion::from_axis_angle(&Vector3::y_axis(), self.auto_rotate_speed * dt);
+            ctx.request_repaint();
+        }
+
+        // Scroll => zoom toward the cursor
+        let scroll = input.raw_scroll_delta.y;
+        if scroll.abs() > f32::EPSILON {
+            self.transition = None;
+            // The point under the cursor at the orbit center's depth, as an
+            // offset from the center. Uses last frame's viewport.
+            let cursor = input.pointer.hover_pos().filter(|p| self.viewport.contains(*p));
+            let offset = cursor.map_or(egui::Vec2::ZERO, |cursor| cursor - self.viewport.center());
+            let before = self.screen_to_world(self.viewport, offset);
+            self.dist *= (1.0 - scroll * 0.001).max(0.05);
+            self.dist = self.dist.clamp(self.min_dist, self.max_dist);
+            // Zooming scales that offset with `dist`; move the center to keep
+            // the point in place
+            self.pivot += before - self.screen_to_world(self.viewport, offset);
+        }
+        // Keyboard shortcuts, unless a text field is being typed into
+        if !ctx.wants_keyboard_input() {
+            // Home => back to the default pose. Shift jumps there instead of
+            // gliding, here and for the preset views.
+            let snap = input.modifiers.shift;
+            if input.key_pressed(egui::Key::Home) {
+                self.reset_view(snap);
+            }
+
+            // Ctrl+V with a copied camera on the clipboard => go to that viewpoint
+            for event in &input.raw.events {
+                if let egui::Event::Paste(text) = event {
+                    if !self.paste_camera(text, snap) {
+                        self.status = "The clipboard doesn't hold a copied camera".into();
+                    }
+                }
+            }
+
+            // Arrows nudge the rotation, PageUp/PageDown step the zoom.
+            // Shift makes bigger steps.
+            let (angle, zoom) = if input.modifiers.shift {
+                (NUDGE_ANGLE_LARGE, NUDGE_ZOOM_LARGE)
+            } else {
+                (NUDGE_ANGLE, NUDGE_ZOOM)
+            };
+            // Left/right turn about the world up axis, up/down about the
+            // camera's horizontal axis, like a turntable
+            let turn = |axis: nalgebra::Unit<Vector3<f32>>, a: f32| UnitQuaternion::from_axis_angle(&axis, a);
+            if input.key_pressed(egui::Key::ArrowLeft) {
+                self.orientation *= turn(Vector3::y_axis(), -angle);
+            }
+            if input.key_pressed(egui::Key::ArrowRight) {
+                self.orientation *= turn(Vector3::y_axis(), angle);
+            }
+            if input.key_pressed(egui::Key::ArrowUp) {
+                self.orientation = turn(Vector3::x_axis(), angle) * self.orientation;
+            }
+            if input.key_pressed(egui::Key::ArrowDown) {
+                self.orientation = turn(Vector3::x_axis(), -angle) * self.orientation;
+            }
+            // Fly mode: held movement keys glide the camera, pivot and all
+            if self.navigation == Navigation::Fly && !input.modifiers.command {
+                let to_world = self.orientation.inverse();
+                let forward = camera_forward(self.orientation);
+                let (right, up) = (to_world * Vector3::x(), Vector3::y());
+                let mut step = Vector3::zeros();
+                for (key, direction) in [
+                    (egui::Key::W, forward),
+                    (egui::Key::S, -forward),
+                    (egui::Key::D, right),
+                    (egui::Key::A, -right),
+                    (egui::Key::E, up),
+                    (egui::Key::Q, -up),
+                ] {
+                    if input.key_down(key) {
+                        step += direction;
+                    }
+                }
+                if let Some(step) = step.try_normalize(f32::EPSILON) {
+                    self.transition = None;
+                    self.pivot += step * self.fly_speed * dt;
+                    ctx.request_repaint();
+                }
+            }
+            // W/G/X/N/C flip the common view options, with a toast saying
+            // which way. W moves forward when flying, so it doesn't toggle then.
+            if input.modifiers.is_none() {
+                let mut toggled = None;
+                if input.key_pressed(egui::Key::W) && self.navigation == Navigation::Orbit {
+                    if self.render_mode == RenderMode::Wireframe {
+                        self.render_mode = self.solid_render_mode;
+                    } else {
+                        self.solid_render_mode = self.render_mode;
+                        self.render_mode = RenderMode::Wireframe;
+                    }
+                    toggled = Some(("Wireframe", self.render_mode == RenderMode::Wireframe));
+                }
+                if input.key_pressed(egui::Key::G) {
+                    self.show_grid = !self.show_grid;
+                    toggled = Some(("Grid", self.show_grid));
+                }
+                if input.key_pressed(egui::Key::X) {
+                    self.show_axes = !self.show_axes;
+                    toggled = Some(("Axes", self.show_axes));
+                }
+                if input.key_pressed(egui::Key::N) {
+                    self.show_normals = !self.show_normals;
+                    toggled = Some(("Normals", self.show_normals));
+                }
+                if input.key_pressed(egui::Key::C) {
+                    self.cull_mode = if self.cull_mode == CullMode::None { CullMode::Back } else { CullMode::None };
+                    toggled = Some(("Backface culling", self.cull_mode != CullMode::None));
+                }
+                if let Some((name, on)) = toggled {
+                    self.toast = Some((format!("{name} {}", if on { "on" } else { "off" }), input.time));
+                    ctx.request_repaint();
+                }
+            }
+            if input.key_pressed(egui::Key::PageUp) {
+                self.dist = (self.dist / zoom).clamp(self.min_dist, self.max_dist);
+            }
+            if input.key_pressed(egui::Key::PageDown) {
+                self.dist = (self.dist * zoom).clamp(self.min_dist, self.max_dist);
+            }
+
+            // Ctrl+Z undoes a scene edit; Ctrl+Y or Ctrl+Shift+Z redoes it
+            let ctrl = input.modifiers.command;
+            if ctrl && input.key_pressed(egui::Key::Z) && !input.modifiers.shift {
+                self.undo();
+            }
+            if ctrl && (input.key_pressed(egui::Key::Y) || (input.key_pressed(egui::Key::Z) && input.modifiers.shift)) {
+                self.redo();
+            }
+
+            // CAD/Blender-style 1/3/7 for front/right/top, Ctrl for the opposite side
+            for (key, view, opposite) in [
+                (egui::Key::Num1, PresetView::Front, PresetView::Back),
+                (egui::Key::Num3, PresetView::Right, PresetView::Left),
+                (egui::Key::Num7, PresetView::Top, PresetView::Bottom),
+            ] {
+                if input.key_pressed(key) {
+                    self.set_preset_view(if ctrl { opposite } else { view }, snap);
+                }
+            }
+        }
+
+        egui::SidePanel::right("settings").show(ctx, |ui| {
+            egui::ScrollArea::vertical().show(ui, |ui| {
+                let toggled = section(ui, "Objects", self.sections.objects, |ui| {
+                    for (i, object) in self.objects.iter_mut().enumerate() {
+                        ui.horizontal(|ui| {
+                            // Hiding only changes which triangles get gathered, not the objects themselves
+                            self.dirty |= ui.checkbox(&mut object.visible, "").on_hover_text("Visible").changed();
+                            egui::color_picker::color_edit_button_srgba(ui, &mut object.color, egui::color_picker::Alpha::OnlyBlend)
+                                .on_hover_text("Color and opacity");
+                            ui.selectable_value(&mut self.selected, Some(i), &object.name);
+                        });
+                    }
+                    ui.horizontal(|ui| {
+                        ui.menu_button("Add", |ui| {
+                            for primitive in Primitive::ALL {
+                                if ui.button(primitive.label()).clicked() {
+                                    self.add_object(primitive);
+                                    ui.close_menu();
+                                }
+                            }
+                        });
+                        if let Some(i) = self.selected {
+                            if ui.button("Remove").clicked() {
+                                self.objects.remove(i);
+                                self.selected = None;
+                                self.dirty = true;
+                            }
+                            if ui.button("Solo").on_hover_text("Hide every other object").clicked() {
+                                for (j, object) in self.objects.iter_mut().enumerate() {
+                                    object.visible = j == i;
+                                }
+                                self.dirty = true;
+                            }
+                        }
+                        if self.objects.iter().any(|o| !o.visible) && ui.button("Show all").clicked() {
+                            for object in &mut self.objects {
+                                object.visible = true;
+                            }
+                            self.dirty = true;
+                        }
+                    });
+                    ui.horizontal(|ui| {
+                        if ui.add_enabled(!self.undo_stack.is_empty(), egui::Button::new("Undo")).on_hover_text("Ctrl+Z").clicked() {
+                            self.undo();
+                        }
+                        if ui.add_enabled(!self.redo_stack.is_empty(), egui::Button::new("Redo")).on_hover_text("Ctrl+Y").clicked() {
+                            self.redo();
+                        }
+                        ui.add(egui::DragValue::new(&mut self.history_limit).range(1..=1000).prefix("keep "))
+                            .on_hover_text("Maximum number of undo steps");
+                    });
+                    if self.objects.len() > 1 {
+                        self.dirty |= ui.add(egui::Slider::new(&mut self.explode, 0.0..=2.0).text("Explode")).changed();
+                    }
+                    ui.checkbox(&mut self.import_replaces, "Imports replace the scene");
+                    ui.checkbox(&mut self.show_gizmo, "Move gizmo").on_hover_text("Drag an arm to move the selected object along it");
+                });
+                self.sections.objects ^= toggled;
+                let toggled = section(ui, "Geometry", self.sections.geometry, |ui| {
+                    let selected = self.selected.and_then(|i| self.objects.get_mut(i));
+                    if let Some(mesh) = selected.as_ref().and_then(|o| o.mesh.as_ref()) {
+                        ui.label(format!("Imported from {}", mesh.path.display()));
+                        ui.label(format!("{} triangles", mesh.triangles.len()));
+                    }
+                    if let Some(object) = selected.filter(|o| o.mesh.is_none()) {
+                        let before = (object.a, object.op, object.b);
+                        egui::ComboBox::from_label("A").selected_text(object.a.label()).show_ui(ui, |ui| {
+                            for primitive in Primitive::ALL {
+                                ui.selectable_value(&mut object.a, primitive, primitive.label());
+                            }
+                        });
+                        egui::ComboBox::from_label("Operation")
+                            .selected_text(object.op.label())
+                            .show_ui(ui, |ui| {
+                                for op in BooleanOp::ALL {
+                                    ui.selectable_value(&mut object.op, op, op.label());
+                                }
+                            });
+                        egui::ComboBox::from_label("B")
+                            .selected_text(object.b.map_or("None", Primitive::label))
+                            .show_ui(ui, |ui| {
+                                ui.selectable_value(&mut object.b, None, "None");
+                                for primitive in Primitive::ALL {
+                                    ui.selectable_value(&mut object.b, Some(primitive), primitive.label());
+                                }
+                            });
+                        // Sliders report a change only on frames where the value moved
+                        let mut changed = (object.a, object.op, object.b) != before;
+                        let params = &mut object.params;
+                        let used = [Some(object.a), object.b];
+                        if used.contains(&Some(Primitive::Cube)) {
+                            changed |= ui.add(egui::Slider::new(&mut params.cube_size, 0.1..=3.0).text("Cube size")).changed();
+                        }
+                        if used.contains(&Some(Primitive::Sphere)) {
+                            changed |= ui.add(egui::Slider::new(&mut params.sphere_radius, 0.1..=3.0).text("Sphere radius")).changed();
+                            changed |= ui.add(egui::Slider::new(&mut params.sphere_slices, 3..=64).text("Sphere slices")).changed();
+                            changed |= ui.add(egui::Slider::new(&mut params.sphere_stacks, 2..=32).text("Sphere stacks")).changed();
+                        }
+                        if used.contains(&Some(Primitive::Cylinder)) {
+                            changed |= ui.add(egui::Slider::new(&mut params.cylinder_radius, 0.1..=3.0).text("Cylinder radius")).changed();
+                            changed |= ui.add(egui::Slider::new(&mut params.cylinder_height, 0.1..=5.0).text("Cylinder height")).changed();
+                            changed |= ui.add(egui::Slider::new(&mut params.cylinder_segments, 3..=64).text("Cylinder segments")).changed();
+                        }
+                        changed |= ui
+                            .add(egui::Slider::new(&mut params.subdivisions, 0..=4).text("Subdivide"))
+                            .on_hover_text("Each level splits every triangle into four")
+                            .changed();
+                        ui.label(format!("{} triangles", object.triangles.len()));
+                        object.dirty |= changed;
+                        self.dirty |= changed;
+
+                        ui.label("Transform");
+                        let mut moved = false;
+                        let mut row = |ui: &mut egui::Ui, label: &str, v: &mut Vector3<f32>, speed: f64, suffix: &str| {
+                            ui.horizontal(|ui| {
+                                for k in 0..3 {
+                                    let drag = egui::DragValue::new(&mut v[k]).speed(speed).suffix(suffix);
+                                    moved |= ui.add(drag).changed();
+                                }
+                                ui.label(label);
+                            });
+                        };
+                        row(ui, "Position", &mut object.position, 0.01, "");
+                        row(ui, "Rotation", &mut object.rotation, 0.5, "°");
+                        if self.uniform_scale {
+                            let mut s = object.scale.x;
+                            ui.horizontal(|ui| {
+                                if ui.add(egui::DragValue::new(&mut s).speed(0.01).range(0.01..=100.0)).changed() {
+                                    object.scale = Vector3::repeat(s);
+                                    moved = true;
+                                }
+                                ui.label("Scale");
+                            });
+                        } else {
+                            row(ui, "Scale", &mut object.scale, 0.01, "");
+                        }
+                        ui.checkbox(&mut self.uniform_scale, "Uniform scale");
+                        // Both only change the position; the object's own coordinates are kept
+                        ui.horizontal(|ui| {
+                            let center = ui.button("Center").on_hover_text("Move the object so its bounding box is centered on the origin");
+                            let drop = ui.button("Drop to ground").on_hover_text("Move the object straight up or down until it rests on the ground plane");
+                            if center.clicked() || drop.clicked() {
+                                let world: Vec<Triangle> = object.world_triangles(0, Vector3::zeros()).collect();
+                                if let Some((lo, hi)) = bounding_box(&world) {
+                                    if center.clicked() {
+                                        object.position -= (lo + hi) * 0.5;
+                                    } else {
+                                        object.position.y -= lo.y;
+                                    }
+                                    moved = true;
+                                }
+                            }
+                        });
+                        // Only recompose the matrix (and re-gather triangles) on an actual edit
+                        if moved {
+                            object.update_transform();
+                            self.dirty = true;
+                        }
+                    } else {
+                        ui.label("Select an object to edit it.");
+                    }
+                });
+                self.sections.geometry ^= toggled;
+                let toggled = section(ui, "Rendering", self.sections.rendering, |ui| {
+                    ui.horizontal_wrapped(|ui| {
+                        ui.radio_value(&mut self.render_mode, RenderMode::Painter, "Painter");
+                        ui.radio_value(&mut self.render_mode, RenderMode::ZBuffer, "Z-buffer");
+                        ui.radio_value(&mut self.render_mode, RenderMode::Wireframe, "Wireframe");
+                        ui.radio_value(&mut self.render_mode, RenderMode::HiddenLine, "Hidden line");
+                        ui.radio_value(&mut self.render_mode, RenderMode::Points, "Points");
+                        if matches!(self.render_mode, RenderMode::Wireframe | RenderMode::HiddenLine | RenderMode::Points) {
+                            ui.color_edit_button_srgba(&mut self.wire_color);
+                        }
+                        if self.render_mode == RenderMode::Wireframe {
+                            ui.add(egui::DragValue::new(&mut self.wire_width).range(0.5..=5.0).speed(0.05).suffix(" px"));
+                        }
+                        if matches!(self.render_mode, RenderMode::ZBuffer | RenderMode::HiddenLine) {
+                            egui::ComboBox::from_id_salt("supersample")
+                                .selected_text(format!("AA {}×", self.supersample))
+                                .show_ui(ui, |ui| {
+                                    for samples in [1, 2, 4] {
+                                        ui.selectable_value(&mut self.supersample, samples, format!("{samples}×"));
+                                    }
+                                })
+                                .response
+                                .on_hover_text("Supersampling: render at a higher resolution, then scale down");
+                        }
+                        if self.render_mode == RenderMode::Painter {
+                            egui::ComboBox::from_id_salt("edge_strokes")
+                                .selected_text(match self.edge_strokes {
+                                    EdgeStrokes::Auto => "Edges: auto",
+                                    EdgeStrokes::On => "Edges: on",
+                                    EdgeStrokes::Off => "Edges: off",
+                                })
+                                .show_ui(ui, |ui| {
+                                    ui.selectable_value(&mut self.edge_strokes, EdgeStrokes::Auto, "Auto")
+                                        .on_hover_text(format!("Only for models of up to {AUTO_EDGE_LIMIT} triangles"));
+                                    ui.selectable_value(&mut self.edge_strokes, EdgeStrokes::On, "On");
+                                    ui.selectable_value(&mut self.edge_strokes, EdgeStrokes::Off, "Off");
+                                });
+                            if self.edge_strokes != EdgeStrokes::Off {
+                                ui.add(egui::DragValue::new(&mut self.edge_width).range(0.25..=4.0).speed(0.05).suffix(" px"));
+                            }
+                        }
+                        if self.render_mode == RenderMode::Points {
+                            ui.add(egui::DragValue::new(&mut self.point_radius).range(0.5..=8.0).speed(0.05).suffix(" px"));
+                        }
+                    });
+                    ui.horizontal(|ui| {
+                        ui.radio_value(&mut self.shading, ShadingMode::Flat, "Flat");
+                        ui.radio_value(&mut self.shading, ShadingMode::Smooth, "Smooth");
+                    });
+                    egui::ComboBox::from_label("Cull")
+                        .selected_text(match self.cull_mode {
+                            CullMode::None => "None",
+                            CullMode::Back => "Back faces",
+                            CullMode::Front => "Front faces",
+                        })
+                        .show_ui(ui, |ui| {
+                            ui.selectable_value(&mut self.cull_mode, CullMode::None, "None");
+                            ui.selectable_value(&mut self.cull_mode, CullMode::Back, "Back faces");
+                            ui.selectable_value(&mut self.cull_mode, CullMode::Front, "Front faces");
+                        });
+                    egui::ComboBox::from_label("Color")
+                        .selected_text(match self.color_mode {
+                            ColorMode::Object => "Object",
+                            ColorMode::RandomPerFace => "Random per face",
+                            ColorMode::DepthHeatmap => "Depth heatmap",
+                        })
+                        .show_ui(ui, |ui| {
+                            ui.selectable_value(&mut self.color_mode, ColorMode::Object, "Object");
+                            ui.selectable_value(&mut self.color_mode, ColorMode::RandomPerFace, "Random per face");
+                            ui.selectable_value(&mut self.color_mode, ColorMode::DepthHeatmap, "Depth heatmap");
+                        });
+
+                    ui.horizontal(|ui| {
+                        let mut theme = self.theme;
+                        ui.label("Theme");
+                        ui.radio_value(&mut theme, Theme::Dark, "Dark");
+                        ui.radio_value(&mut theme, Theme::Light, "Light");
+                        if theme != self.theme {
+                            self.set_theme(ctx, theme);
+                        }
+                    });
+                    ui.horizontal(|ui| {
+                        ui.color_edit_button_srgba(&mut self.bg_color);
+                        if self.bg_gradient {
+                            ui.color_edit_button_srgba(&mut self.bg_color_bottom);
+                        }
+                        ui.label("Background");
+                        ui.checkbox(&mut self.bg_gradient, "Gradient");
+                    });
+                    ui.checkbox(&mut self.fog_enabled, "Fog");
+                    ui.add_enabled_ui(self.fog_enabled, |ui| {
+                        ui.add(egui::Slider::new(&mut self.fog_start, 0.0..=50.0).text("Fog start"));
+                        ui.add(egui::Slider::new(&mut self.fog_end, 0.0..=100.0).text("Fog end"));
+                    });
+                    self.fog_end = self.fog_end.max(self.fog_start);
+
+                    ui.horizontal(|ui| {
+                        ui.checkbox(&mut self.show_axes, "Axes");
+                        ui.add_enabled(self.show_axes, egui::Checkbox::new(&mut self.axes_gizmo, "Corner gizmo"));
+                    });
+                    ui.horizontal(|ui| {
+                        ui.checkbox(&mut self.show_bbox, "Bounding box");
+                        if let Some((c, r)) = self.sphere {
+                            ui.weak(format!("sphere ({:.2}, {:.2}, {:.2}), radius {r:.2}", c.x, c.y, c.z))
+                                .on_hover_text("Center and radius of a sphere holding the whole model");
+                        }
+                    });
+                    ui.horizontal(|ui| {
+                        ui.checkbox(&mut self.section, "Section plane");
+                        ui.add_enabled_ui(self.section, |ui| {
+                            for (k, (_, _, label)) in AXES.into_iter().enumerate() {
+                                ui.radio_value(&mut self.section_axis, k, label);
+                            }
+                            ui.checkbox(&mut self.section_flip, "Flip");
+                        });
+                    });
+                    let range = self.bounds.map_or(-5.0..=5.0, |(lo, hi)| lo[self.section_axis]..=hi[self.section_axis]);
+                    ui.add_enabled(self.section, egui::Slider::new(&mut self.section_offset, range).clamping(egui::SliderClamping::Never).text("Offset"));
+                    ui.checkbox(&mut self.show_grid, "Ground grid");
+                    ui.add_enabled_ui(self.show_grid, |ui| {
+                        ui.add(egui::Slider::new(&mut self.grid_spacing, 0.05..=5.0).logarithmic(true).text("Spacing"));
+                        ui.add(egui::Slider::new(&mut self.grid_extent, 0.5..=50.0).logarithmic(true).text("Extent"));
+                        ui.checkbox(&mut self.show_shadow, "Shadow").on_hover_text("The model flattened onto the ground along the light");
+                    });
+                    ui.checkbox(&mut self.sphere_lod, "Sphere level of detail")
+                        .on_hover_text("Halve the sphere's slices and stacks at each distance, while zoomed out");
+                    ui.add_enabled_ui(self.sphere_lod, |ui| {
+                        ui.horizontal(|ui| {
+                            let [near, far] = &mut self.lod_distances;
+                            ui.add(egui::DragValue::new(near).range(0.1..=*far).speed(0.05));
+                            ui.add(egui::DragValue::new(far).range(*near..=1000.0).speed(0.1));
+                            ui.label(format!("Distances (level {})", self.lod_level));
+                        });
+                    });
+                });
+                self.sections.rendering ^= toggled;
+                let toggled = section(ui, "Camera", self.sections.camera, |ui| {
+                    ui.horizontal(|ui| {
+                        ui.radio_value(&mut self.projection, Projection::Perspective, "Perspective");
+                        ui.radio_value(&mut self.projection, Projection::Orthographic, "Orthographic");
+                    });
+                    ui.add(egui::Slider::new(&mut self.fov_degrees, 20.0..=90.0).suffix("°").text("Field of view"))
+                        .on_hover_text(
+                            "Vertical field of view. The visible height at distance d is 2·d·tan(fov/2), \
+                             so narrowing the FOV while increasing the distance keeps the model the same \
+                             size but flattens the perspective (a dolly zoom). Orthographic mode uses the \
+                             same relation at the orbit center.",
+                        );
+                    ui.add(egui::Slider::new(&mut self.far, 0.5..=DEFAULT_FAR_Z).logarithmic(true).text("Far plane"));
+                    ui.horizontal_wrapped(|ui| {
+                        for view in PresetView::ALL {
+                            if ui.button(view.label()).on_hover_text("Shift+click to snap").clicked() {
+                                self.set_preset_view(view, ui.input(|i| i.modifiers.shift));
+                            }
+                        }
+                    });
+                    ui.horizontal(|ui| {
+                        ui.label(format!("Pivot ({:.2}, {:.2}, {:.2})", self.pivot.x, self.pivot.y, self.pivot.z))
+                            .on_hover_text("Double-click the model to orbit around that point");
+                        if ui.small_button("Reset").on_hover_text("Back to the middle of the model").clicked() {
+                            self.pivot = self.model_center();
+                        }
+                    });
+                    if ui.button("Copy camera").on_hover_text("Paste it back with Ctrl+V").clicked() {
+                        self.copy_camera(ctx);
+                    }
+                    ui.horizontal(|ui| {
+                        ui.label("Zoom range");
+                        ui.add(egui::DragValue::new(&mut self.min_dist).range(NEAR_Z..=self.max_dist).speed(0.01));
+                        ui.add(egui::DragValue::new(&mut self.max_dist).range(self.min_dist..=1000.0).speed(0.1));
+                    });
+                    ui.horizontal(|ui| {
+                        ui.radio_value(&mut self.navigation, Navigation::Orbit, "Orbit");
+                        ui.radio_value(&mut self.navigation, Navigation::Fly, "Fly")
+                            .on_hover_text("Drag to look around, W/A/S/D to move, Q/E to go down/up");
+                        if self.navigation == Navigation::Fly {
+                            ui.add(egui::DragValue::new(&mut self.fly_speed).range(0.05..=100.0).speed(0.05).suffix(" /s"))
+                                .on_hover_text("Flying speed, in world units per second");
+                        }
+                    });
+                    ui.checkbox(&mut self.pan_with_secondary, "Right-drag pans (middle-drag always does)");
+                    ui.add(egui::Slider::new(&mut self.rotate_sensitivity, 0.1..=5.0).logarithmic(true).text("Rotate speed"));
+                    ui.checkbox(&mut self.invert_y, "Invert vertical rotation");
+                    ui.add(egui::Slider::new(&mut self.pan_sensitivity, 0.05..=5.0).logarithmic(true).text("Pan speed"));
+                    ui.horizontal(|ui| {
+                        ui.checkbox(&mut self.inertia, "Inertia");
+                        ui.add_enabled(
+                            self.inertia,
+                            egui::Slider::new(&mut self.inertia_damping, 0.5..=20.0).logarithmic(true).text("Damping"),
+                        );
+                    });
+                });
+                self.sections.camera ^= toggled;
+                let toggled = section(ui, "Measure", self.sections.measure, |ui| {
+                    ui.horizontal(|ui| {
+                        ui.toggle_value(&mut self.measuring, "Measure").on_hover_text("Click two points on the model");
+                        ui.checkbox(&mut self.measure_snap, "Snap to vertices");
+                        if ui.add_enabled(!self.measure.is_empty(), egui::Button::new("Clear")).clicked() {
+                            self.measure.clear();
+                        }
+                    });
+                    match self.measure[..] {
+                        [a, b] => {
+                            let d = b - a;
+                            ui.label(format!("Distance {:.4}", d.norm()));
+                            ui.label(format!("ΔX {:.4}  ΔY {:.4}  ΔZ {:.4}", d.x, d.y, d.z));
+                        }
+                        [_] => {
+                            ui.label("Click the second point");
+                        }
+                        _ => {
+                            ui.label(if self.measuring { "Click the first point" } else { "No measurement" });
+                        }
+                    }
+                });
+                self.sections.measure ^= toggled;
+                let toggled = section(ui, "Lighting", self.sections.lighting, |ui| {
+                    ui.checkbox(&mut self.headlight, "Headlight (follow camera)");
+                    ui.add_enabled_ui(!self.headlight, |ui| {
+                        ui.label("Direction (toward the light)");
+                        ui.add(egui::Slider::new(&mut self.light_dir.x, -1.0..=1.0).text("x"));
+                        ui.add(egui::Slider::new(&mut self.light_dir.y, -1.0..=1.0).text("y"));
+                        ui.add(egui::Slider::new(&mut self.light_dir.z, -1.0..=1.0).text("z"));
+                    });
+                    ui.add(egui::Slider::new(&mut self.light_intensity, 0.0..=2.0).text("Intensity"));
+                    ui.add(egui::Slider::new(&mut self.ambient, 0.0..=1.0).text("Ambient"));
+                    ui.add(egui::Slider::new(&mut self.diffuse, 0.0..=1.0).text("Diffuse"));
+                    ui.add(egui::Slider::new(&mut self.specular, 0.0..=1.0).text("Specular"));
+                    ui.add(egui::Slider::new(&mut self.shininess, 1.0..=128.0).logarithmic(true).text("Shininess"));
+                });
+                self.sections.lighting ^= toggled;
+                let toggled = section(ui, "Debug", self.sections.debug, |ui| {
+                    ui.horizontal(|ui| {
+                        ui.checkbox(&mut self.show_normals, "Normals");
+                        ui.add_enabled(
+                            self.show_normals,
+                            egui::DragValue::new(&mut self.normal_length).range(0.001..=10.0).speed(0.005).prefix("length "),
+                        );
+                    });
+                    ui.checkbox(&mut self.highlight_hover, "Highlight hovered triangle");
+                    ui.checkbox(&mut self.show_fps, "Frame rate overlay");
+                    let threads = if cfg!(feature = "rayon") { "parallel" } else { "serial" };
+                    ui.label(format!("Triangle setup: {:.2} ms ({threads})", self.render_time.as_secs_f64() * 1000.0))
+                        .on_hover_text("Time to light, clip and project the triangles when the view last changed");
+                    ui.label(format!("Outside the view: {} triangles", self.render_stats.outside));
+                });
+                self.sections.debug ^= toggled;
+            });
+        });
+
+        // Once a drag or click is over, whatever it changed becomes one undo step
+        if !input.pointer.any_down() {
+            self.commit_edits();
+        }
+
+        // Spheres get coarser or finer as the camera distance crosses a threshold
+        let lod = if self.sphere_lod { lod_level(self.dist, self.lod_distances, self.lod_level) } else { 0 };
+        if lod != self.lod_level {
+            self.lod_level = lod;
+            for object in self.objects.iter_mut().filter(|o| o.uses(Primitive::Sphere)) {
+                object.dirty = true;
+                self.dirty = true;
+            }
+        }
+
+        // Swap in geometry finished on worker threads. Results for objects
+        // removed in the meantime match nothing and are dropped.
+        for built in self.build_receiver.try_iter() {
+            if let Some(object) = self.objects.iter_mut().find(|o| o.pending == Some(built.ticket)) {
+                object.finish_build(built);
+                self.dirty = true;
+            }
+        }
+
+        // Geometry settings above may have changed; rebuild before drawing
+        if self.dirty {
+            self.rebuild();
+        }
 
-type CSG = csgrs::csg::CSG<()>;
+        egui::TopBottomPanel::bottom("status").show(ctx, |ui| {
+            ui.horizontal(|ui| {
+                // The spinner keeps repainting, so finished builds get picked up
+                if self.objects.iter().any(|o| o.pending.is_some()) {
+                    ui.spinner();
+                    ui.label("Building…");
+                    ui.separator();
+                }
+                ui.label(if self.status.is_empty() { "Ready" } else { &self.status });
+                ui.with_layout(egui::Layout::right_to_left(egui::Align::Center), |ui| {
+                    let drawn = self.render_cache.as_ref().map_or(0, |(_, render_tris)| render_tris.len());
+                    ui.label(format!(
+                        "{} triangles, {drawn} drawn, {} cut by the near plane",
+                        self.triangles.len(),
+                        self.render_stats.near_clipped
+                    ))
+                    .on_hover_text("Drawn counts the pieces left after culling and clipping, as of the last frame");
+                });
+            });
+        });
 
-/// A small struct holding your geometry plus camera controls.
-struct MyApp {
-    /// Triangles of the model (each triangle is [p0, p1, p2]), in `f32`.
-    /// We store them here so we don’t have to rebuild them every frame.
-    triangles: Vec<([f32; 3], [f32; 3], [f32; 3])>,
+        egui::CentralPanel::default().show(ctx, |ui| {
+            ui.label("Left-drag = rotate, Middle/Right-drag = pan, Scroll/PgUp/PgDn = zoom, arrows = nudge, double-click = orbit around point, 1/3/7 (+Ctrl) = preset views, Home = reset view, Ctrl+V = paste a copied camera, W/G/X/N/C = toggle wireframe/grid/axes/normals/culling.");
+            ui.horizontal(|ui| {
+                ui.menu_button("Scene", |ui| {
+                    if ui.button("Open…").clicked() {
+                        ui.close_menu();
+                        self.open_scene();
+                    }
+                    if ui.button("Save…").clicked() {
+                        ui.close_menu();
+                        self.save_scene();
+                    }
+                });
+                if ui.button("Open STL…").on_hover_text("Or drop .stl files onto the window").clicked() {
+                    self.open_stl();
+                }
+                ui.menu_button("Export", |ui| {
+                    if ui.button("STL (binary)…").clicked() {
+                        ui.close_menu();
+                        self.export_stl();
+                    }
+                    if ui.button("Wavefront OBJ…").clicked() {
+                        ui.close_menu();
+                        self.export_obj();
+                    }
+                    if ui.button("SVG drawing of the view…").clicked() {
+                        ui.close_menu();
+                        self.export_svg();
+                    }
+                });
+                if ui.button("Screenshot…").clicked() {
+                    self.save_screenshot(ctx);
+                }
+                egui::ComboBox::from_id_salt("screenshot_scale")
+                    .width(40.0)
+                    .selected_text(format!("{}×", self.screenshot_scale))
+                    .show_ui(ui, |ui| {
+                        for scale in [1, 2, 4] {
+                            ui.selectable_value(&mut self.screenshot_scale, scale, format!("{scale}×"));
+                        }
+                    })
+                    .response
+                    .on_hover_text("1× captures the window as shown; larger sizes re-render the model alone");
+                if ui.button("Fit to view").clicked() {
+                    self.fit_requested = true;
+                }
+                if ui.button("Reset view").on_hover_text("Home (Shift to snap)").clicked() {
+                    self.reset_view(ui.input(|i| i.modifiers.shift));
+                }
+                ui.toggle_value(&mut self.auto_rotate, "Turntable");
+                if self.auto_rotate {
+                    ui.add(egui::DragValue::new(&mut self.auto_rotate_speed).range(-5.0..=5.0).speed(0.01).suffix(" rad/s"));
+                }
+            });
 
-    /// Camera angles (yaw, pitch), in radians.
-    yaw: f32,
-    pitch: f32,
-    /// Camera distance from origin
-    dist: f32,
+            // Allocate a region to draw in, under a fixed id so input handling
+            // can ask whether it's the widget being dragged
+            let (rect, _) = ui.allocate_exact_size(ui.available_size(), egui::Sense::hover());
+            let response = ui.interact(rect, viewport_id(), egui::Sense::click_and_drag());
+            let painter = ui.painter_at(rect);
 
-    /// Pan offset in the plane. Adjusts the final 2D position of the projection.
-    pan_x: f32,
-    pan_y: f32,
-}
+            // Before layout settles on the first frame, or while the window is
+            // collapsed, there's no room to draw and the aspect ratio is
+            // meaningless. Input has already been handled above; keep the last
+            // usable viewport for it and try again shortly. Not every frame:
+            // a collapsed window would spin at full speed.
+            if rect.width() < 1.0 || rect.height() < 1.0 {
+                ctx.request_repaint_after(EMPTY_VIEW_RETRY);
+                return;
+            }
 
-impl MyApp {
-    fn new() -> Self {
-        // 1) Build some geometry from csgrs
-        let cube = CSG::cube(1.0, 1.0, 1.0, None);
-        let sphere = CSG::sphere(1.0, 16, 8, None);
+            // We'll do a "painter's algorithm" in the 2D space of this region.
+            self.viewport = rect;
+            let center_2d = rect.center();
+            if self.bg_gradient {
+                let mut mesh = Mesh::default();
+                mesh.colored_vertex(rect.left_top(), self.bg_color);
+                mesh.colored_vertex(rect.right_top(), self.bg_color);
+                mesh.colored_vertex(rect.right_bottom(), self.bg_color_bottom);
+                mesh.colored_vertex(rect.left_bottom(), self.bg_color_bottom);
+                mesh.add_triangle(0, 1, 2);
+                mesh.add_triangle(0, 2, 3);
+                painter.add(Shape::mesh(mesh));
+            } else {
+                painter.rect_filled(rect, 0.0, self.bg_color);
+            }
 
-        // Union, then subdivide for more triangles
-        let unioned = cube.union(&sphere);
+            // The button only records the request; fitting needs the viewport size
+            if std::mem::take(&mut self.fit_requested) {
+                self.fit_to_view(rect);
+            }
+
+            // Build a view transform from orientation, pivot, dist (the same
+            // one `render_tris` uses)
+            let cam = build_camera(self.orientation, self.pivot, self.dist);
+            let aspect = viewport_aspect(rect);
+            let proj = self.projection_matrix(aspect);
+            // Pixels per unit of normalized device coordinates, per axis
+            let half_w = rect.width() * 0.5;
+            let half_h = rect.height() * 0.5;
+            let far = self.far;
 
-        // 2) Gather triangle list in f32
-        let mut triangles_f32 = Vec::new();
-        for poly in &unioned.polygons {
-            // Triangulate each polygon (most are already triangles after `subdivide_triangles`)
-            let tri_list = poly.triangulate();
-            for tri in tri_list {
-                let p0 = tri[0].pos;
-                let p1 = tri[1].pos;
-                let p2 = tri[2].pos;
-                triangles_f32.push((
-                    [p0.x as f32, p0.y as f32, p0.z as f32],
-                    [p1.x as f32, p1.y as f32, p1.z as f32],
-                    [p2.x as f32, p2.y as f32, p2.z as f32],
-                ));
+            // Convert 2D coords to egui::Pos2
+            let to_screen = |p: [f32; 2]| Pos2::new(center_2d.x + p[0] * half_w, center_2d.y + p[1] * half_h);
+            // Take a world-space segment through the same camera, clipping and
+            // projection as the triangles. Used for lines overlaid on the model.
+            let world_line = |a: Vector3<f32>, b: Vector3<f32>| {
+                let (a, b) = clip_segment(transform(cam, a.into()), transform(cam, b.into()), NEAR_Z, far)?;
+                Some([to_screen(project(proj, a)), to_screen(project(proj, b))])
+            };
+
+            // The ground grid goes underneath everything so the model covers it
+            if self.show_grid {
+                let stroke = Stroke::new(1.0, Color32::from_white_alpha(40));
+                let spacing = self.grid_spacing.max(1e-3);
+                let lines = (self.grid_extent / spacing).floor() as i32;
+                let extent = lines as f32 * spacing;
+                for i in -lines..=lines {
+                    let offset = i as f32 * spacing;
+                    for (a, b) in [
+                        (Vector3::new(offset, 0.0, -extent), Vector3::new(offset, 0.0, extent)),
+                        (Vector3::new(-extent, 0.0, offset), Vector3::new(extent, 0.0, offset)),
+                    ] {
+                        if let Some(seg) = world_line(a, b) {
+                            painter.line_segment(seg, stroke);
+                        }
+                    }
+                }
+            }
+            if self.show_grid && self.show_shadow {
+                let light = self.light();
+                let key = Some((self.geometry_epoch, light));
+                if self.shadow_key != key {
+                    self.shadow = ground_shadow(&self.triangles, light);
+                    self.shadow_key = key;
+                }
+                // Flattened faces rarely overlap (see `ground_shadow`), so one
+                // translucent color gives a mostly even shade
+                let color = Color32::from_black_alpha(60);
+                let mut mesh = Mesh::default();
+                for tri in &self.shadow {
+                    let corners = tri.map(|p| ClipVertex { pos: transform(cam, p.into()), shade: 0.0, specular: 0.0 });
+                    let clipped = clip_polygon(&corners, |v| v.z - NEAR_Z);
+                    let clipped = clip_polygon(&clipped, |v| far - v.z);
+                    let first = mesh.vertices.len() as u32;
+                    for c in &clipped {
+                        mesh.colored_vertex(to_screen(project(proj, c.pos)), color);
+                    }
+                    for i in 1..clipped.len().saturating_sub(1) as u32 {
+                        mesh.add_triangle(first, first + i, first + i + 1);
+                    }
+                }
+                painter.add(Shape::mesh(mesh));
             }
-        }
 
-        Self {
-            triangles: triangles_f32,
-            yaw: 0.0,
-            pitch: 0.0,
-            dist: 3.0,  // camera distance
-            pan_x: 0.0,
-            pan_y: 0.0,
-        }
-    }
-}
+            // Reuse last frame's triangles while nothing they depend on changed
+            let (key, mut render_tris) = self.take_render_tris(rect);
 
-impl eframe::App for MyApp {
-    fn update(&mut self, ctx: &egui::Context, _frame: &mut eframe::Frame) {
-        // Respond to mouse input for rotation, panning, zooming:
-        let input = ctx.input(|i| i.clone());
+            // Depth span of the visible set, so the heatmap always covers the model
+            let depth_range = depth_range(&render_tris);
+
+            match self.render_mode {
+                RenderMode::Painter => {
+                    // Sort back-to-front by depth span. Every depth is finite here, and
+                    // `sort_by_key` is stable, so ties keep their mesh order.
+                    //
+                    // Note this is still a painter's algorithm: no per-triangle ordering
+                    // can resolve cyclic overlaps or interpenetrating triangles.
+                    render_tris.sort_by_key(RenderTri::depth_key);
+
+                    // Draw them as one mesh, which keeps the order. Each triangle's
+                    // edges follow its fill in the same mesh, so nearer faces still
+                    // cover the edges of farther ones.
+                    let strokes = match self.edge_strokes {
+                        EdgeStrokes::Auto => self.triangles.len() <= AUTO_EDGE_LIMIT,
+                        EdgeStrokes::On => true,
+                        EdgeStrokes::Off => false,
+                    };
+                    let mut mesh = Mesh::default();
+                    let per_tri = if strokes { (7, 15) } else { (1, 3) };
+                    mesh.reserve_triangles(render_tris.len() * per_tri.0);
+                    mesh.reserve_vertices(render_tris.len() * per_tri.1);
+                    for tri in &render_tris {
+                        let corners = tri.pts.map(to_screen);
+                        let colors = self.tri_colors(tri, depth_range);
+                        let first = mesh.vertices.len() as u32;
+                        for (c, color) in corners.into_iter().zip(colors) {
+                            mesh.colored_vertex(c, color);
+                        }
+                        mesh.add_triangle(first, first + 1, first + 2);
+                        if strokes {
+                            let area = signed_area_2d(corners.map(|c| [c.x, c.y])).abs() * 0.5;
+                            let edge_color = self.wire_color.gamma_multiply((area / EDGE_FADE_AREA).min(1.0));
+                            for (i, j) in [(0, 1), (1, 2), (2, 0)] {
+                                add_line_quad(&mut mesh, corners[i], corners[j], self.edge_width, edge_color);
+                            }
+                        }
+                    }
+                    painter.add(Shape::mesh(mesh));
+                }
+                RenderMode::ZBuffer | RenderMode::HiddenLine => {
+                    // Rasterize at physical pixel resolution so the result stays
+                    // crisp, times the supersampling factor
+                    let samples = self.supersample;
+                    let width = (rect.width() * ctx.pixels_per_point()).round().max(0.0) as usize * samples;
+                    let height = (rect.height() * ctx.pixels_per_point()).round().max(0.0) as usize * samples;
+                    let ppp = ctx.pixels_per_point() * samples as f32;
+                    self.zbuffer.clear(width, height, Color32::TRANSPARENT);
+                    let to_pixel = |p: [f32; 2]| {
+                        let c = to_screen(p) - rect.min;
+                        [c.x * ppp, c.y * ppp]
+                    };
+
+                    if self.render_mode == RenderMode::ZBuffer {
+                        let mut zbuffer = std::mem::replace(&mut self.zbuffer, ZBuffer::new(0, 0));
+                        self.fill_zbuffer(&mut zbuffer, &render_tris, to_pixel, depth_range);
+                        self.zbuffer = zbuffer;
+                    } else {
+                        // Faces only occlude: the background shows through, but
+                        // their depth hides edges behind
+                        for tri in &render_tris {
+                            self.zbuffer.fill_depth(tri.pts.map(to_pixel), tri.z);
+                        }
+                        for tri in &render_tris {
+                            let pts = tri.pts.map(to_pixel);
+                            for (i, j) in [(0, 1), (1, 2), (2, 0)] {
+                                self.zbuffer.draw_line(pts[i], pts[j], [tri.z[i], tri.z[j]], samples, self.wire_color);
+                            }
+                        }
+                    }
 
-        if input.pointer.is_decidedly_dragging() {
-            if let drag_delta = input.pointer.delta() {
-                // Left click => rotate
-                // Right click => pan
-                if input.pointer.button_down(egui::PointerButton::Primary) {
-                    self.yaw -= drag_delta.x * 0.01;   // turn left-right
-                    self.pitch += drag_delta.y * 0.01; // turn up-down
-                } else if input.pointer.button_down(egui::PointerButton::Secondary) {
-                    self.pan_x += drag_delta.x * 0.5;
-                    self.pan_y += drag_delta.y * 0.5;
+                    // Upload and blit as a single textured rectangle
+                    let image = self.zbuffer.to_image(samples);
+                    let texture = match &mut self.zbuffer_texture {
+                        Some(texture) => {
+                            texture.set(image, egui::TextureOptions::NEAREST);
+                            texture
+                        }
+                        None => self.zbuffer_texture.insert(ctx.load_texture(
+                            "zbuffer",
+                            image,
+                            egui::TextureOptions::NEAREST,
+                        )),
+                    };
+                    let uv = egui::Rect::from_min_max(Pos2::ZERO, Pos2::new(1.0, 1.0));
+                    painter.image(texture.id(), rect, uv, Color32::WHITE);
+                }
+                RenderMode::Points => {
+                    for &p in &self.points {
+                        let v = transform(cam, p);
+                        if v.z < NEAR_Z || v.z > far {
+                            continue;
+                        }
+                        let c = to_screen(project(proj, v));
+                        painter.add(Shape::circle_filled(c, self.point_radius, self.wire_color));
+                    }
+                }
+                RenderMode::Wireframe => {
+                    // No fill means no occlusion, so draw order doesn't matter
+                    let stroke = PathStroke::new(self.wire_width, self.wire_color);
+                    for tri in &render_tris {
+                        let [c0, c1, c2] = tri.pts.map(to_screen);
+                        painter.add(Shape::line(vec![c0, c1, c2, c0], stroke.clone()));
+                    }
                 }
             }
-        }
-        // Scroll => zoom
-        let scroll = input.raw_scroll_delta.y;
-        if scroll.abs() > f32::EPSILON {
-            self.dist *= (1.0 - scroll * 0.001).max(0.05);
-        }
+            // Painter mode left them sorted, so the next sort has little to do
+            self.render_cache = Some((key, render_tris));
 
-        egui::CentralPanel::default().show(ctx, |ui| {
-            ui.label("Left-drag = rotate, Right-drag = pan, Scroll = zoom.");
+            // Outline the cut, so the section reads as a slice rather than a hole
+            if let Some(plane) = self.section_plane() {
+                let key = Some((self.geometry_epoch, plane));
+                if self.section_outline_key != key {
+                    self.section_outline = section_loops(&self.triangles, plane);
+                    self.section_outline_key = key;
+                }
+                let stroke = Stroke::new(2.0, Color32::from_rgb(255, 90, 40));
+                for chain in &self.section_outline {
+                    for ab in chain.windows(2) {
+                        if let Some(seg) = world_line(ab[0], ab[1]) {
+                            painter.line_segment(seg, stroke);
+                        }
+                    }
+                }
+            }
+
+            // Overlays, drawn on top of the model without depth testing
+            if self.show_normals {
+                let stroke = Stroke::new(1.0, Color32::from_rgb(255, 220, 0));
+                for tri in &self.triangles {
+                    let [p0, p1, p2] = tri.pos.map(Vector3::from);
+                    let centroid = (p0 + p1 + p2) / 3.0;
+                    let tip = centroid + tri.face * self.normal_length;
+                    if let Some(seg) = world_line(centroid, tip) {
+                        painter.line_segment(seg, stroke);
+                    }
+                }
+            }
 
-            // Allocate a region to draw in
-            let available_size = ui.available_size();
-            let (response, painter) = ui.allocate_painter(available_size, egui::Sense::drag());
+            // Overdraw the frontmost triangle under the cursor, and say which one it is
+            let hovered = response.hover_pos().filter(|_| self.highlight_hover && !response.dragged());
+            if let Some((index, _)) = hovered.and_then(|pos| self.pick(rect, pos)) {
+                let tri = &self.triangles[index];
+                let color = Color32::from_rgb(0, 255, 255);
+                let corners = tri.pos.map(|p| transform(cam, p));
+                if corners.iter().all(|c| (NEAR_Z..=far).contains(&c.z)) {
+                    let pts = corners.map(|c| to_screen(project(proj, c))).to_vec();
+                    painter.add(Shape::convex_polygon(pts, color.gamma_multiply(0.4), Stroke::NONE));
+                }
+                let [p0, p1, p2] = tri.pos.map(Vector3::from);
+                for (a, b) in [(p0, p1), (p1, p2), (p2, p0)] {
+                    if let Some(seg) = world_line(a, b) {
+                        painter.line_segment(seg, Stroke::new(2.0, color));
+                    }
+                }
+                let centroid = (p0 + p1 + p2) / 3.0;
+                let object = &self.objects[tri.object].name;
+                response.clone().on_hover_ui_at_pointer(|ui| {
+                    ui.label(format!("Triangle #{index} ({object})"));
+                    ui.label(format!("Centroid ({:.3}, {:.3}, {:.3})", centroid.x, centroid.y, centroid.z));
+                });
+            }
 
-            // We'll do a "painter's algorithm" in the 2D space of this region.
-            let rect = response.rect;
-            let center_2d = rect.center();
+            // Measurement clicks, then the measured span, tracking the camera
+            if let Some(pos) = response.interact_pointer_pos().filter(|_| self.measuring && response.clicked()) {
+                if let Some((index, hit)) = self.pick(rect, pos) {
+                    let point = if self.measure_snap {
+                        let corners = self.triangles[index].pos.map(Vector3::from);
+                        corners.into_iter().min_by(|a, b| (a - hit).norm().total_cmp(&(b - hit).norm())).unwrap_or(hit)
+                    } else {
+                        hit
+                    };
+                    if self.measure.len() == 2 {
+                        self.measure.clear();
+                    }
+                    self.measure.push(point);
+                }
+            }
+            let measure_color = Color32::from_rgb(255, 200, 40);
+            if let [a, b] = self.measure[..] {
+                if let Some(seg) = world_line(a, b) {
+                    painter.line_segment(seg, Stroke::new(2.0, measure_color));
+                }
+                if let Some(mid) = self.world_to_screen(rect, (a + b) * 0.5) {
+                    let galley = painter.layout_no_wrap(format!("{:.4}", (b - a).norm()), egui::FontId::proportional(14.0), Color32::WHITE);
+                    let pos = mid + egui::vec2(-galley.size().x * 0.5, -galley.size().y - 6.0);
+                    let frame = egui::Rect::from_min_size(pos, galley.size()).expand2(egui::vec2(4.0, 2.0));
+                    painter.rect_filled(frame, 3.0, Color32::from_black_alpha(180));
+                    painter.galley(pos, galley, Color32::WHITE);
+                }
+            }
+            for &p in &self.measure {
+                if let Some(c) = self.world_to_screen(rect, p) {
+                    painter.circle_filled(c, 4.0, measure_color);
+                }
+            }
+
+            if let (true, Some((lo, hi))) = (self.show_bbox, self.bounds) {
+                let stroke = Stroke::new(1.0, Color32::from_rgb(255, 120, 220));
+                // Corner `i` takes max on axis k when bit k of i is set
+                let corner = |i: usize| {
+                    Vector3::new(
+                        if i & 1 != 0 { hi.x } else { lo.x },
+                        if i & 2 != 0 { hi.y } else { lo.y },
+                        if i & 4 != 0 { hi.z } else { lo.z },
+                    )
+                };
+                // The 12 edges join corners that differ in exactly one bit
+                for i in 0..8 {
+                    for bit in [1, 2, 4] {
+                        if i & bit == 0 {
+                            if let Some(seg) = world_line(corner(i), corner(i | bit)) {
+                                painter.line_segment(seg, stroke);
+                            }
+                        }
+                    }
+                }
+            }
+
+            if self.show_axes {
+                let font = egui::FontId::monospace(12.0);
+                let axes = AXES.map(|(axis, color, label)| (Vector3::from(axis), color, label));
+                if self.axes_gizmo {
+                    // Fixed-size gizmo in the bottom-left corner: rotation only,
+                    // with screen Y growing downward like `project`.
+                    let size = 40.0;
+                    let origin = rect.left_bottom() + egui::vec2(size + 16.0, -(size + 16.0));
+                    let rot = build_camera(self.orientation, Vector3::zeros(), 0.0);
+                    for (axis, color, label) in axes {
+                        let d = transform(rot, axis.into());
+                        let tip = origin + egui::vec2(d.x, -d.y) * size;
+                        painter.line_segment([origin, tip], Stroke::new(2.0, color));
+                        painter.text(tip, egui::Align2::CENTER_CENTER, label, font.clone(), color);
+                    }
+                } else {
+                    for (axis, color, label) in axes {
+                        if let Some([a, b]) = world_line(Vector3::zeros(), axis * AXES_LENGTH) {
+                            painter.line_segment([a, b], Stroke::new(2.0, color));
+                            painter.text(b, egui::Align2::LEFT_BOTTOM, label, font.clone(), color);
+                        }
+                    }
+                }
+            }
+
+            // Translate gizmo on the selected object, the grabbed or hovered arm brightened
+            if let Some((origin, tips)) = self.gizmo_handles(rect) {
+                let hovered = response.hover_pos().and_then(|pos| {
+                    (0..3).find(|&k| distance_to_segment(pos, origin, tips[k]) <= GIZMO_GRAB_RADIUS)
+                });
+                for (k, (_, color, _)) in AXES.into_iter().enumerate() {
+                    let active = self.gizmo_axis.or(hovered) == Some(k);
+                    let (width, color) = if active { (4.0, Color32::WHITE.lerp_to_gamma(color, 0.5)) } else { (2.5, color) };
+                    painter.line_segment([origin, tips[k]], Stroke::new(width, color));
+                    painter.circle_filled(tips[k], width + 1.5, color);
+                }
+                painter.circle_filled(origin, 3.0, Color32::WHITE);
+            }
+
+            // An empty scene would otherwise just be a blank view. Objects
+            // still building will have triangles in a moment.
+            if self.triangles.is_empty() && self.objects.iter().all(|o| o.pending.is_none()) {
+                let color = self.wire_color.gamma_multiply(0.6);
+                painter.text(rect.center(), egui::Align2::CENTER_CENTER, "No geometry", egui::FontId::proportional(18.0), color);
+            }
+
+            if let Some((text, shown)) = &self.toast {
+                let age = input.time - shown;
+                if age < TOAST_SECONDS {
+                    let alpha = ((TOAST_SECONDS - age) / 0.5).min(1.0) as f32;
+                    let galley = painter.layout_no_wrap(text.clone(), egui::FontId::proportional(14.0), Color32::WHITE);
+                    let pos = rect.center_top() + egui::vec2(-galley.size().x * 0.5, 12.0);
+                    let frame = egui::Rect::from_min_size(pos, galley.size()).expand2(egui::vec2(10.0, 6.0));
+                    painter.rect_filled(frame, 6.0, Color32::from_black_alpha(180).gamma_multiply(alpha));
+                    painter.galley_with_override_text_color(pos, galley, Color32::WHITE.gamma_multiply(alpha));
+                    ctx.request_repaint();
+                } else {
+                    self.toast = None;
+                }
+            }
 
-            // Build a view transform from yaw, pitch, dist
-            let cam = build_camera(self.yaw, self.pitch, self.dist);
-
-            // We'll collect "renderable triangles" in a small vec
-            let mut render_tris = Vec::new();
-            for &(p0, p1, p2) in &self.triangles {
-                // Transform each vertex by `cam`
-                let v0 = transform(cam, p0);
-                let v1 = transform(cam, p1);
-                let v2 = transform(cam, p2);
-
-                // We'll store the average depth, plus the 3 points in 2D
-                let avg_z = (v0.z + v1.z + v2.z) / 3.0;
-                // Some basic shading factor based on average Z (or normal, if we want).
-                // For demonstration, let's darken further-away triangles:
-                let shade = (1.0 - (avg_z / 10.0)).clamp(0.0, 1.0);
-
-                // Projection: simple scale
-                fn project(pos: Vector3<f32>) -> Option<[f32; 2]> {
-                    // Choose a near plane (anything behind this is culled):
-                    let near_z = 0.1;
-                    let focal_length = 2.0;  // or whatever "camera" constant you want
-                
-                    // If it's behind the near plane, skip it (or clip partially).
-                    if pos.z < near_z {
-                        return None;
-                    }
-                
-                    // Simple perspective scale:
-                    let scale = focal_length / pos.z;
-                    Some([pos.x * scale, -pos.y * scale])
-                }
-                let p0_2d = project(v0).unwrap();
-                let p1_2d = project(v1).unwrap();
-                let p2_2d = project(v2).unwrap();
-
-                render_tris.push((avg_z, shade, p0_2d, p1_2d, p2_2d));
-            }
-
-            // Sort back-to-front by avg_z (descending)
-            render_tris.sort_by(|a, b| b.0.partial_cmp(&a.0).unwrap_or(std::cmp::Ordering::Equal));
-
-            // Draw them
-            for &(_z, shade, p0, p1, p2) in &render_tris {
-                let color_val = (shade * 200.0) as u8;
-                let col = Color32::from_rgb(color_val, color_val, color_val);
-
-                // Convert 2D coords to egui::Pos2, with pan offset
-                let c0 = Pos2::new(
-                    center_2d.x + p0[0] * 100.0 + self.pan_x,
-                    center_2d.y + p0[1] * 100.0 + self.pan_y,
-                );
-                let c1 = Pos2::new(
-                    center_2d.x + p1[0] * 100.0 + self.pan_x,
-                    center_2d.y + p1[1] * 100.0 + self.pan_y,
-                );
-                let c2 = Pos2::new(
-                    center_2d.x + p2[0] * 100.0 + self.pan_x,
-                    center_2d.y + p2[1] * 100.0 + self.pan_y,
-                );
-
-                // Fill the triangle
-                let path = vec![c0, c1, c2, c0];
-                //painter.add(Shape::closed_line(path, PathStroke::new(1.0, col)));
-                //painter.add(Shape::convex_polygon(
-                //    vec![c0, c1, c2],
-                //    Color32::from_rgb(50, 100, 255), // fill color
-                //    Stroke::NONE,
-                //));
-                painter.add(Shape::convex_polygon(
-                    vec![c0, c1, c2],
-                    Color32::from_rgb(50, 100, 255),
-                    Stroke::new(1.0, Color32::from_rgb(255, 255, 255)),
-                ));
+            if self.show_fps {
+                self.paint_performance(&painter, rect);
+                // egui only repaints on input; keep frames coming so there's something to measure
+                ctx.request_repaint();
             }
         });
     }
 }
 
-/// A helper: build a camera transform matrix (4x4) from yaw, pitch, distance.
-/// For simplicity, we do just rotates and then translate along +Z.
-fn build_camera(yaw: f32, pitch: f32, dist: f32) -> Matrix4<f32> {
-    // Rotation around Y (yaw), then X (pitch).
-    let rot_y = Matrix4::new_rotation(Vector3::y() * yaw);
-    let rot_x = Matrix4::new_rotation(Vector3::x() * pitch);
-    let translate = Matrix4::new_translation(&Vector3::new(0.0, 0.0, dist));
-    translate * rot_x * rot_y
+/// A helper: build a camera transform matrix (4x4) that orbits `pivot` at
+/// `dist`, looking straight at it. Moves the pivot to the origin, rotates,
+/// then backs away along the view axis.
+///
+/// The world is right-handed, like csgrs, and `orientation` turns it into a
+/// right-handed view frame with X right, Y up and the camera looking down -Z
+/// (so the identity looks from +Z). Camera space, what the rest of the
+/// pipeline works in, then flips Z so depth is positive in front of the
+/// camera, which makes it left-handed. Without that flip models would show
+/// up mirrored.
+fn build_camera(orientation: UnitQuaternion<f32>, pivot: Vector3<f32>, dist: f32) -> Matrix4<f32> {
+    let flip_z = Matrix4::new_nonuniform_scaling(&Vector3::new(1.0, 1.0, -1.0));
+    let back_away = Matrix4::new_translation(&Vector3::new(0.0, 0.0, -dist));
+    flip_z * back_away * orientation.to_homogeneous() * Matrix4::new_translation(&-pivot)
+}
+
+/// The orientation of a classic orbit camera: turn around Y (yaw), then X (pitch).
+fn orientation_from_yaw_pitch(yaw: f32, pitch: f32) -> UnitQuaternion<f32> {
+    UnitQuaternion::from_axis_angle(&Vector3::x_axis(), pitch) * UnitQuaternion::from_axis_angle(&Vector3::y_axis(), yaw)
+}
+
+/// World-space direction the camera built by `build_camera` is looking along.
+fn camera_forward(orientation: UnitQuaternion<f32>) -> Vector3<f32> {
+    // The camera looks down -Z of the view frame; undo the view rotation.
+    orientation.inverse() * -Vector3::z()
+}
+
+/// Map a screen position onto the virtual trackball filling `viewport`, as a
+/// unit vector in the view frame `orientation` rotates into (see
+/// `build_camera`). The ball's visible front faces the camera (+Z);
+/// positions outside it land on its silhouette.
+fn arcball_point(viewport: egui::Rect, pos: Pos2) -> Vector3<f32> {
+    let radius = (viewport.width().min(viewport.height()) * 0.5).max(1.0);
+    let offset = (pos - viewport.center()) / radius;
+    // Screen Y grows downward, camera-space Y upward
+    let (x, y) = (offset.x, -offset.y);
+    let len2 = x * x + y * y;
+    if len2 <= 1.0 {
+        Vector3::new(x, y, (1.0 - len2).sqrt())
+    } else {
+        Vector3::new(x, y, 0.0) / len2.sqrt()
+    }
+}
+
+/// Build a perspective projection matrix for a camera looking down +Z.
+/// Depths in `[near, far]` map to normalized device Z in `[-1, 1]`, and
+/// `w` ends up holding the camera-space depth for the perspective divide.
+///
+/// Normalized device Y points down like screen space, so camera-space up
+/// lands toward the top of the view.
+fn build_projection(fov_y_radians: f32, aspect: f32, near: f32, far: f32) -> Matrix4<f32> {
+    let f = 1.0 / (fov_y_radians * 0.5).tan();
+    Matrix4::new(
+        f / aspect, 0.0, 0.0, 0.0,
+        0.0, -f, 0.0, 0.0,
+        0.0, 0.0, (far + near) / (far - near), -2.0 * far * near / (far - near),
+        0.0, 0.0, 1.0, 0.0,
+    )
+}
+
+/// Build an orthographic projection matrix for a camera looking down +Z, where
+/// `half_height` world units above and below the axis fill the viewport.
+/// Depths and Y map like in [`build_projection`], but `w` stays 1.
+fn build_orthographic(half_height: f32, aspect: f32, near: f32, far: f32) -> Matrix4<f32> {
+    let half_width = half_height * aspect;
+    Matrix4::new(
+        1.0 / half_width, 0.0, 0.0, 0.0,
+        0.0, -1.0 / half_height, 0.0, 0.0,
+        0.0, 0.0, 2.0 / (far - near), -(far + near) / (far - near),
+        0.0, 0.0, 0.0, 1.0,
+    )
 }
 
 /// Multiply a 3D point by a 4x4 transform, returning a new 3D Vector.
+/// The result is divided by `w` so projective matrices work too; affine
+/// matrices leave `w == 1` and are unaffected.
 fn transform(m: Matrix4<f32>, p: [f32; 3]) -> Vector3<f32> {
     let v = m * Vector4::new(p[0], p[1], p[2], 1.0);
-    Vector3::new(v.x, v.y, v.z)
+    if v.w != 0.0 {
+        Vector3::new(v.x / v.w, v.y / v.w, v.z / v.w)
+    } else {
+        Vector3::new(v.x, v.y, v.z)
+    }
+}
+
+/// Clip a convex polygon against a plane, keeping the part where the signed
+/// distance `dist(pos)` is non-negative. This is one pass of Sutherland–Hodgman.
+/// The result has 0 vertices (fully clipped), or 3+ vertices.
+fn clip_polygon(poly: &[ClipVertex], dist: impl Fn(Vector3<f32>) -> f32) -> Vec<ClipVertex> {
+    let mut out = Vec::with_capacity(poly.len() + 1);
+    for i in 0..poly.len() {
+        let cur = poly[i];
+        let next = poly[(i + 1) % poly.len()];
+        let d_cur = dist(cur.pos);
+        let d_next = dist(next.pos);
+
+        if d_cur >= 0.0 {
+            out.push(cur);
+        }
+        // Edge crosses the plane => emit the intersection point
+        if (d_cur >= 0.0) != (d_next >= 0.0) {
+            let t = d_cur / (d_cur - d_next);
+            out.push(cur.lerp(&next, t));
+        }
+    }
+    out
+}
+
+/// Width over height of a viewport. The rect can have zero height on the very
+/// first frame, before layout has settled, so that falls back to square.
+fn viewport_aspect(rect: egui::Rect) -> f32 {
+    if rect.height() > 0.0 {
+        rect.width() / rect.height()
+    } else {
+        1.0
+    }
+}
+
+/// Clip a camera-space line segment to the depth range `[near, far]`.
+/// Returns `None` if no part of it is inside.
+fn clip_segment(a: Vector3<f32>, b: Vector3<f32>, near: f32, far: f32) -> Option<(Vector3<f32>, Vector3<f32>)> {
+    let (mut t0, mut t1) = (0.0f32, 1.0f32);
+    // Clip the parametric range against each plane in turn
+    for (dist_a, dist_b) in [(a.z - near, b.z - near), (far - a.z, far - b.z)] {
+        if dist_a < 0.0 && dist_b < 0.0 {
+            return None;
+        }
+        if dist_a < 0.0 {
+            t0 = t0.max(dist_a / (dist_a - dist_b));
+        } else if dist_b < 0.0 {
+            t1 = t1.min(dist_a / (dist_a - dist_b));
+        }
+    }
+    if t0 > t1 {
+        return None;
+    }
+    let d = b - a;
+    Some((a + d * t0, a + d * t1))
+}
+
+/// Which bit of a vertex's frustum outcode is the near plane, the fifth of
+/// `frustum_planes`.
+const NEAR_PLANE_BIT: u8 = 1 << 4;
+
+/// The bit `MyApp::render_tris` adds to a vertex's frustum outcode when the
+/// section plane hides it.
+const SECTION_BIT: u8 = 1 << 6;
+
+/// World-space planes bounding what `proj * cam` shows, between the near
+/// plane and `far`. A homogeneous
+/// point `p` is inside when `plane.dot(&p) >= 0` for every plane.
+fn frustum_planes(cam: Matrix4<f32>, proj: Matrix4<f32>, far: f32) -> [Vector4<f32>; 6] {
+    // Each plane is a combination of rows of the matrix, as `x/w >= -1`
+    // is `x + w >= 0` before the divide
+    let clip = proj * cam;
+    let row = |m: Matrix4<f32>, i: usize| m.row(i).transpose();
+    let (x, y, w) = (row(clip, 0), row(clip, 1), row(clip, 3));
+    let depth = row(cam, 2);
+    [
+        x + w,
+        w - x,
+        y + w,
+        w - y,
+        depth - Vector4::new(0.0, 0.0, 0.0, NEAR_Z),
+        Vector4::new(0.0, 0.0, 0.0, far) - depth,
+    ]
+}
+
+/// Project a camera-space point to normalized device coordinates, which
+/// grow rightward and downward like screen space.
+/// Expects a point that has already been clipped against the near plane.
+fn project(proj: Matrix4<f32>, pos: Vector3<f32>) -> [f32; 2] {
+    let ndc = transform(proj, [pos.x, pos.y, pos.z]);
+    [ndc.x, ndc.y]
+}
+
+/// Append the segment from `a` to `b` to `mesh` as a `width` wide quad.
+/// Unlike a stroke shape it isn't anti-aliased, but it batches.
+fn add_line_quad(mesh: &mut Mesh, a: Pos2, b: Pos2, width: f32, color: Color32) {
+    let side = (b - a).normalized().rot90() * (width * 0.5);
+    let first = mesh.vertices.len() as u32;
+    for p in [a - side, a + side, b + side, b - side] {
+        mesh.colored_vertex(p, color);
+    }
+    mesh.add_triangle(first, first + 1, first + 2);
+    mesh.add_triangle(first, first + 2, first + 3);
+}
+
+/// Level of detail for a camera at `dist`, where level `i + 1` starts at
+/// `thresholds[i]`. Crossing a threshold takes going `LOD_HYSTERESIS` past
+/// it from the `current` level, so hovering there doesn't flip back and forth.
+fn lod_level(dist: f32, thresholds: [f32; 2], current: u32) -> u32 {
+    let mut level = 0;
+    for (i, threshold) in (0..).zip(thresholds) {
+        let margin = if current > i { 1.0 - LOD_HYSTERESIS } else { 1.0 + LOD_HYSTERESIS };
+        if dist > threshold * margin {
+            level = i + 1;
+        }
+    }
+    level
+}
+
+/// Id of the 3D view's drag-sensing region.
+fn viewport_id() -> egui::Id {
+    egui::Id::new("viewport")
+}
+
+/// A collapsible settings panel section, expanded if `open`. Returns whether
+/// its header was clicked, which should toggle `open` for the next frame.
+fn section(ui: &mut egui::Ui, title: &str, open: bool, add_contents: impl FnOnce(&mut egui::Ui)) -> bool {
+    let response = egui::CollapsingHeader::new(title).open(Some(open)).show(ui, add_contents);
+    response.header_response.clicked()
+}
+
+/// Distance in points from `p` to the segment from `a` to `b`.
+fn distance_to_segment(p: Pos2, a: Pos2, b: Pos2) -> f32 {
+    let ab = b - a;
+    let t = ((p - a).dot(ab) / ab.length_sq().max(f32::EPSILON)).clamp(0.0, 1.0);
+    p.distance(a + ab * t)
+}
+
+/// The first of `triangles` hit by the ray from `ray_origin` along
+/// `ray_dir`, as its index, the ray parameter `t` of the hit (the distance,
+/// for a unit `ray_dir`) and the hit point.
+fn pick_triangle(ray_origin: Vector3<f32>, ray_dir: Vector3<f32>, triangles: &[Triangle]) -> Option<(usize, f32, Vector3<f32>)> {
+    let (index, t) = triangles
+        .iter()
+        .enumerate()
+        .filter_map(|(i, tri)| Some((i, ray_triangle(ray_origin, ray_dir, tri.pos)?)))
+        .min_by(|a, b| a.1.total_cmp(&b.1))?;
+    Some((index, t, ray_origin + ray_dir * t))
+}
+
+/// Möller–Trumbore ray/triangle intersection. Returns the ray parameter `t`
+/// of the hit (the point is `origin + dir * t`), or `None` for a miss or a
+/// hit behind the origin. Both sides of the triangle count.
+fn ray_triangle(origin: Vector3<f32>, dir: Vector3<f32>, [a, b, c]: [[f32; 3]; 3]) -> Option<f32> {
+    let (a, b, c) = (Vector3::from(a), Vector3::from(b), Vector3::from(c));
+    let e1 = b - a;
+    let e2 = c - a;
+    let p = dir.cross(&e2);
+    let det = e1.dot(&p);
+    if det.abs() < f32::EPSILON {
+        return None; // ray parallel to the triangle, or degenerate triangle
+    }
+    let inv_det = 1.0 / det;
+    let s = origin - a;
+    let u = s.dot(&p) * inv_det;
+    if !(0.0..=1.0).contains(&u) {
+        return None;
+    }
+    let q = s.cross(&e1);
+    let v = dir.dot(&q) * inv_det;
+    if v < 0.0 || u + v > 1.0 {
+        return None;
+    }
+    let t = e2.dot(&q) * inv_det;
+    (t >= 0.0).then_some(t)
+}
+
+/// Where `triangles` cross `plane` (see `MyApp::section_plane`), as chains
+/// of world-space points. A chain that closes repeats its first point at
+/// the end.
+fn section_loops(triangles: &[Triangle], plane: Vector4<f32>) -> Vec<Vec<Vector3<f32>>> {
+    let side = |p: [f32; 3]| plane.dot(&Vector4::new(p[0], p[1], p[2], 1.0));
+    // Each crossing is worked out from the edge's corners in a fixed order,
+    // so the two triangles sharing an edge agree on it bit for bit
+    let crossing = |a: [f32; 3], b: [f32; 3]| {
+        let (a, b) = if a.map(f32::to_bits) <= b.map(f32::to_bits) { (a, b) } else { (b, a) };
+        let (da, db) = (side(a), side(b));
+        Vector3::from(a).lerp(&Vector3::from(b), da / (da - db))
+    };
+    let segments: Vec<[Vector3<f32>; 2]> = triangles
+        .iter()
+        .filter_map(|tri| {
+            let mut ends = [(0, 1), (1, 2), (2, 0)]
+                .into_iter()
+                .filter(|&(i, j)| (side(tri.pos[i]) < 0.0) != (side(tri.pos[j]) < 0.0))
+                .map(|(i, j)| crossing(tri.pos[i], tri.pos[j]));
+            Some([ends.next()?, ends.next()?])
+        })
+        .collect();
+
+    // Join segments that share an end into chains
+    let key = |p: &Vector3<f32>| [p.x.to_bits(), p.y.to_bits(), p.z.to_bits()];
+    let mut ends_at: std::collections::HashMap<[u32; 3], Vec<usize>> = std::collections::HashMap::new();
+    for (i, segment) in segments.iter().enumerate() {
+        for p in segment {
+            ends_at.entry(key(p)).or_default().push(i);
+        }
+    }
+    let mut used = vec![false; segments.len()];
+    let mut chains = Vec::new();
+    for start in 0..segments.len() {
+        if std::mem::replace(&mut used[start], true) {
+            continue;
+        }
+        let mut chain = segments[start].to_vec();
+        // Grow from the last point, then turn around and grow from the first
+        for _ in 0..2 {
+            while let Some(&next) = chain.last().and_then(|end| ends_at[&key(end)].iter().find(|&&i| !used[i])) {
+                used[next] = true;
+                let [a, b] = segments[next];
+                let end = key(&chain[chain.len() - 1]);
+                chain.push(if key(&a) == end { b } else { a });
+            }
+            chain.reverse();
+        }
+        chains.push(chain);
+    }
+    chains
+}
+
+/// The triangles of `triangles` facing `light` (a unit direction toward
+/// it), flattened onto the Y=0 ground plane along it. For a closed model
+/// those cover its shadow about once. Empty when the light is at or below
+/// the horizon.
+fn ground_shadow(triangles: &[Triangle], light: Vector3<f32>) -> Vec<[Vector3<f32>; 3]> {
+    if light.y < 1e-3 {
+        return Vec::new();
+    }
+    let flatten = |p: [f32; 3]| {
+        let p = Vector3::from(p);
+        p - light * (p.y / light.y)
+    };
+    triangles.iter().filter(|tri| tri.face.dot(&light) > 0.0).map(|tri| tri.pos.map(flatten)).collect()
+}
+
+/// Axis-aligned bounds `(min, max)` of all corners, or `None` if there are none.
+fn bounding_box(triangles: &[Triangle]) -> Option<(Vector3<f32>, Vector3<f32>)> {
+    let mut corners = triangles.iter().flat_map(|t| t.pos).map(Vector3::from);
+    let first = corners.next()?;
+    Some(corners.fold((first, first), |(lo, hi), p| (lo.inf(&p), hi.sup(&p))))
+}
+
+/// A sphere `(center, radius)` holding every corner: centered on the
+/// bounding box, which is cheap and close to the smallest for the boxy and
+/// round shapes csgrs makes. A point at the origin when there are no corners.
+fn bounding_sphere(triangles: &[Triangle]) -> (Vector3<f32>, f32) {
+    let center = bounding_box(triangles).map_or_else(Vector3::zeros, |(lo, hi)| (lo + hi) * 0.5);
+    let radius = triangles
+        .iter()
+        .flat_map(|t| t.pos)
+        .map(|p| (Vector3::from(p) - center).norm())
+        .fold(0.0f32, f32::max);
+    (center, radius)
+}
+
+/// Every distinct corner position in `triangles`, in first-seen order, and
+/// each triangle's corners as indices into that list. Positions are compared
+/// bit-for-bit, which is exact for shared corners.
+fn index_vertices(triangles: &[Triangle]) -> (Vec<[f32; 3]>, Vec<[u32; 3]>) {
+    let mut seen = std::collections::HashMap::new();
+    let mut vertices = Vec::new();
+    let indices = triangles
+        .iter()
+        .map(|t| {
+            t.pos.map(|p| {
+                *seen.entry(p.map(f32::to_bits)).or_insert_with(|| {
+                    vertices.push(p);
+                    vertices.len() as u32 - 1
+                })
+            })
+        })
+        .collect();
+    (vertices, indices)
+}
+
+/// Unit normal of a counter-clockwise triangle, or zero if it's degenerate.
+fn face_normal(p0: [f32; 3], p1: [f32; 3], p2: [f32; 3]) -> Vector3<f32> {
+    let a = Vector3::from(p0);
+    let e1 = Vector3::from(p1) - a;
+    let e2 = Vector3::from(p2) - a;
+    e1.cross(&e2).try_normalize(f32::EPSILON).unwrap_or_else(Vector3::zeros)
+}
+
+/// `(min, max)` camera depth over all corners, or an empty (inverted) range.
+fn depth_range(tris: &[RenderTri]) -> (f32, f32) {
+    tris.iter().fold((f32::INFINITY, f32::NEG_INFINITY), |(lo, hi), t| (lo.min(t.min_z()), hi.max(t.max_z())))
+}
+
+/// Encode an image as an 8-bit RGBA PNG.
+fn write_png(path: &std::path::Path, image: &egui::ColorImage) -> Result<(), Box<dyn std::error::Error>> {
+    let file = std::io::BufWriter::new(std::fs::File::create(path)?);
+    let [width, height] = image.size;
+    let mut encoder = png::Encoder::new(file, width as u32, height as u32);
+    encoder.set_color(png::ColorType::Rgba);
+    encoder.set_depth(png::BitDepth::Eight);
+    let data: Vec<u8> = image.pixels.iter().flat_map(|c| c.to_srgba_unmultiplied()).collect();
+    encoder.write_header()?.write_image_data(&data)?;
+    Ok(())
 }
 
-// We need Vector4 too
-use nalgebra::Vector4;
+/// Twice the signed area of a 2D triangle in Y-down coordinates.
+/// Positive means clockwise on screen.
+fn signed_area_2d([a, b, c]: [[f32; 2]; 3]) -> f32 {
+    (b[0] - a[0]) * (c[1] - a[1]) - (b[1] - a[1]) * (c[0] - a[0])
+}
+
+/// Scale the RGB channels of `c` by `shade` in `[0, 1]`, then add a white
+/// specular highlight on top. The opacity stays as it was.
+fn lit_color(c: Color32, shade: f32, specular: f32) -> Color32 {
+    let [r, g, b, a] = c.to_srgba_unmultiplied();
+    let s = |v: u8| (v as f32 * shade + 255.0 * specular).round().clamp(0.0, 255.0) as u8;
+    Color32::from_rgba_unmultiplied(s(r), s(g), s(b), a)
+}
+
+/// How much of the fog color to blend in at camera depth `z`: 0 before `start`,
+/// rising exponentially to exactly 1 at `end`.
+fn fog_amount(z: f32, start: f32, end: f32) -> f32 {
+    const DENSITY: f32 = 3.0;
+    let t = ((z - start) / (end - start).max(f32::EPSILON)).clamp(0.0, 1.0);
+    (1.0 - (-DENSITY * t).exp()) / (1.0 - (-DENSITY).exp())
+}
+
+/// A bright, pseudo-random color that only depends on `seed`.
+fn random_color(seed: usize) -> Color32 {
+    // SplitMix64 finalizer: cheap, and neighbouring seeds land far apart
+    let mut x = (seed as u64).wrapping_add(0x9e37_79b9_7f4a_7c15);
+    x = (x ^ (x >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    x = (x ^ (x >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+    x ^= x >> 31;
+    let hue = (x & 0xffff) as f32 / 65535.0;
+    egui::ecolor::Hsva::new(hue, 0.75, 0.95, 1.0).into()
+}
+
+/// Blue-to-red gradient for `t` in `[0, 1]`, through cyan, green and yellow.
+fn heatmap_color(t: f32) -> Color32 {
+    let hue = (1.0 - t.clamp(0.0, 1.0)) * (2.0 / 3.0);
+    egui::ecolor::Hsva::new(hue, 1.0, 1.0, 1.0).into()
+}
 
 fn main() -> eframe::Result<()> {
+    // An STL given on the command line replaces the demo model
+    let mesh = std::env::args_os().nth(1).and_then(|arg| {
+        let path = std::path::PathBuf::from(arg);
+        match mesh_io::read_stl(&path) {
+            Ok(triangles) => Some(ImportedMesh { path, triangles }),
+            Err(err) => {
+                eprintln!("Couldn't load {}: {err}", path.display());
+                None
+            }
+        }
+    });
+
     let options = eframe::NativeOptions::default();
     eframe::run_native(
         "CSG Viewer (egui)",
         options,
-        Box::new(|_cc| Ok(Box::new(MyApp::new()))),
+        Box::new(|cc| {
+            let mut app = MyApp::new(cc.storage, mesh);
+            cc.egui_ctx.set_visuals(app.theme.visuals());
+            app.watcher = MeshWatcher::new(cc.egui_ctx.clone())
+                .inspect_err(|err| eprintln!("Couldn't watch files for changes: {err}"))
+                .ok();
+            Ok(Box::new(app))
+        }),
     )
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn test_rect() -> egui::Rect {
+        egui::Rect::from_min_size(Pos2::ZERO, egui::vec2(800.0, 600.0))
+    }
+
+    /// The default cube, from (0, 0, 0) to (1, 1, 1).
+    fn cube() -> Vec<Triangle> {
+        scene::csg_triangles(&scene::PrimitiveParams::default().build(Primitive::Cube))
+    }
+
+    fn assert_close(actual: Vector3<f32>, expected: Vector3<f32>) {
+        assert!((actual - expected).norm() < 1e-5, "expected {expected:?}, got {actual:?}");
+    }
+
+    #[test]
+    fn transform_divides_by_w() {
+        // 90° FOV gives f = 1; depths 1..3 map to NDC Z -1..1
+        let proj = build_projection(std::f32::consts::FRAC_PI_2, 1.0, 1.0, 3.0);
+        // Clip space (1, -2, 1, 2), divided by w = 2
+        assert_close(transform(proj, [1.0, 2.0, 2.0]), Vector3::new(0.5, -1.0, 0.5));
+        assert_close(transform(proj, [0.0, 0.0, 1.0]), Vector3::new(0.0, 0.0, -1.0));
+        assert_close(transform(proj, [3.0, 0.0, 3.0]), Vector3::new(1.0, 0.0, 1.0));
+    }
+
+    #[test]
+    fn transform_passes_w_zero_through() {
+        let m = Matrix4::from_diagonal(&Vector4::new(2.0, 3.0, 4.0, 0.0));
+        assert_close(transform(m, [1.0, 1.0, 1.0]), Vector3::new(2.0, 3.0, 4.0));
+    }
+
+    /// The unit right triangle in the plane `z`, moved `x` along X.
+    fn flat_triangle(x: f32, z: f32) -> Triangle {
+        Triangle::new([[x, 0.0, z], [x + 1.0, 0.0, z], [x, 1.0, z]], [[0.0; 3]; 3], 0)
+    }
+
+    #[test]
+    fn pick_triangle_hits_known_triangle() {
+        let triangles = [flat_triangle(10.0, 0.0), flat_triangle(0.0, 0.0)];
+        let (index, t, hit) = pick_triangle(Vector3::new(0.25, 0.25, 2.0), -Vector3::z(), &triangles).unwrap();
+        assert_eq!(index, 1);
+        assert!((t - 2.0).abs() < 1e-5, "t = {t}");
+        assert_close(hit, Vector3::new(0.25, 0.25, 0.0));
+    }
+
+    #[test]
+    fn pick_triangle_misses_outside_edges() {
+        // Inside the unit square but past the hypotenuse
+        let hit = pick_triangle(Vector3::new(0.75, 0.75, 2.0), -Vector3::z(), &[flat_triangle(0.0, 0.0)]);
+        assert!(hit.is_none());
+    }
+
+    #[test]
+    fn pick_triangle_ignores_parallel_ray() {
+        let hit = pick_triangle(Vector3::new(-1.0, 0.25, 0.0), Vector3::x(), &[flat_triangle(0.0, 0.0)]);
+        assert!(hit.is_none());
+    }
+
+    #[test]
+    fn pick_triangle_ignores_hit_behind_origin() {
+        let hit = pick_triangle(Vector3::new(0.25, 0.25, -2.0), -Vector3::z(), &[flat_triangle(0.0, 0.0)]);
+        assert!(hit.is_none());
+    }
+
+    #[test]
+    fn pick_triangle_prefers_nearer_of_stacked() {
+        let triangles = [flat_triangle(0.0, 0.0), flat_triangle(0.0, 1.0)];
+        let (index, t, _) = pick_triangle(Vector3::new(0.25, 0.25, 5.0), -Vector3::z(), &triangles).unwrap();
+        assert_eq!(index, 1);
+        assert!((t - 4.0).abs() < 1e-5, "t = {t}");
+    }
+
+    #[test]
+    fn unchanged_view_reuses_cached_triangles() {
+        let mut app = MyApp::new(None, None);
+        app.set_triangles(cube());
+        let rect = test_rect();
+        let (key, render_tris) = app.take_render_tris(rect);
+        let (buffer, pts) = (render_tris.as_ptr(), render_tris.iter().map(|t| t.pts).collect::<Vec<_>>());
+        assert!(!render_tris.is_empty());
+        app.render_cache = Some((key, render_tris));
+
+        // Same key: the very same buffer comes back
+        let (again, render_tris) = app.take_render_tris(rect);
+        assert!(again == key);
+        assert_eq!(render_tris.as_ptr(), buffer, "an unchanged view should reuse the cached triangles");
+        app.render_cache = Some((again, render_tris));
+
+        // A turned camera makes a new key and new triangles
+        app.orientation = orientation_from_yaw_pitch(0.3, 0.0);
+        let (turned, render_tris) = app.take_render_tris(rect);
+        assert!(turned != key);
+        assert_ne!(render_tris.iter().map(|t| t.pts).collect::<Vec<_>>(), pts);
+        app.render_cache = Some((turned, render_tris));
+
+        // So does new geometry, even seen from the same place
+        app.geometry_epoch += 1;
+        assert!(app.render_key(rect) != turned);
+    }
+
+    #[test]
+    fn camera_moves_leave_scene_clean() {
+        let mut app = MyApp::new(None, None);
+        app.rebuild();
+        app.orientation = orientation_from_yaw_pitch(1.0, 0.5);
+        app.pivot = Vector3::new(1.0, 2.0, 3.0);
+        app.dist = 7.0;
+        app.set_preset_view(PresetView::Top, true);
+        app.fit_to_view(test_rect());
+        app.reset_view(true);
+        assert!(!app.dirty);
+    }
+
+    #[test]
+    fn preset_view_undoes_pan() {
+        let mut app = MyApp::new(None, None);
+        app.set_triangles(cube());
+        app.pivot += app.screen_to_world(test_rect(), egui::vec2(120.0, -40.0));
+        app.set_preset_view(PresetView::Top, true);
+        assert_eq!(app.pivot, app.model_center());
+        assert_close(app.pivot, Vector3::repeat(0.5));
+        assert_eq!(app.dist, DEFAULT_DIST);
+    }
+
+    #[test]
+    fn cube_faces_point_outward() {
+        let triangles = cube();
+        assert_eq!(triangles.len(), 12);
+        let middle = Vector3::repeat(0.5);
+        for tri in &triangles {
+            // One of the six axis directions...
+            let axis = tri.face.iamax();
+            assert!((tri.face[axis].abs() - 1.0).abs() < 1e-5, "{:?} isn't axis-aligned", tri.face);
+            // ...away from the middle of the cube...
+            let centroid = tri.pos.iter().map(|&p| Vector3::from(p)).sum::<Vector3<f32>>() / 3.0;
+            assert!((centroid - middle).dot(&tri.face) > 0.0, "{:?} points inward", tri.face);
+            // ...and the way csgrs' own normals say
+            for n in tri.normal {
+                assert!(Vector3::from(n).dot(&tri.face) > 0.99, "{:?} disagrees with {n:?}", tri.face);
+            }
+        }
+    }
+
+    /// A perspective and an orthographic projection with the app's defaults.
+    fn both_projections() -> [Matrix4<f32>; 2] {
+        [
+            build_projection(DEFAULT_FOV_DEGREES.to_radians(), 1.5, NEAR_Z, DEFAULT_FAR_Z),
+            build_orthographic(2.0, 1.5, NEAR_Z, DEFAULT_FAR_Z),
+        ]
+    }
+
+    #[test]
+    fn camera_space_up_projects_toward_top() {
+        for proj in both_projections() {
+            let [x, y] = project(proj, Vector3::new(0.0, 1.0, 5.0));
+            assert!(x.abs() < 1e-6);
+            assert!(y < 0.0, "NDC y {y} should be above the center");
+        }
+    }
+
+    #[test]
+    fn home_camera_centers_origin_right_handed() {
+        let home = CameraPose::home();
+        let cam = build_camera(home.orientation, home.pivot, home.dist);
+        let to_ndc = |proj, p: [f32; 3]| project(proj, transform(cam, p));
+        for proj in both_projections() {
+            let [x, y] = to_ndc(proj, [0.0, 0.0, 0.0]);
+            assert!(x.abs() < 1e-6 && y.abs() < 1e-6, "origin at NDC ({x}, {y})");
+            // Looking from +Z, world +X is to the right and +Y is up
+            assert!(to_ndc(proj, [1.0, 0.0, 0.0])[0] > 0.0);
+            assert!(to_ndc(proj, [0.0, 1.0, 0.0])[1] < 0.0);
+        }
+        assert!(transform(cam, [0.0, 0.0, 0.0]).z > 0.0, "the origin should be in front of the camera");
+    }
+
+    #[test]
+    fn bounding_sphere_of_cube() {
+        let (center, radius) = bounding_sphere(&cube());
+        assert_close(center, Vector3::repeat(0.5));
+        assert!((radius - 3.0f32.sqrt() / 2.0).abs() < 1e-5, "radius {radius}");
+    }
+
+    #[test]
+    fn bounding_sphere_of_off_center_mesh() {
+        let offset = Vector3::new(100.0, -50.0, 20.0);
+        let moved: Vec<Triangle> = cube()
+            .iter()
+            .map(|tri| Triangle::new(tri.pos.map(|p| (Vector3::from(p) + offset).into()), tri.normal, 0))
+            .collect();
+        let (center, radius) = bounding_sphere(&moved);
+        assert_close(center, offset + Vector3::repeat(0.5));
+        assert!((radius - 3.0f32.sqrt() / 2.0).abs() < 1e-4, "radius {radius}");
+        assert_eq!(bounding_sphere(&[]), (Vector3::zeros(), 0.0));
+    }
+
+    #[test]
+    fn empty_scene_leaves_camera_alone() {
+        let mut app = MyApp::new(None, None);
+        app.objects.clear();
+        app.selected = None;
+        app.rebuild();
+        let before = app.pose();
+        app.fit_to_view(test_rect());
+        assert!(app.bounds.is_none() && app.sphere.is_none());
+        let after = app.pose();
+        assert_eq!(after.orientation, before.orientation);
+        assert_eq!(after.pivot, before.pivot);
+        assert_eq!(after.dist, before.dist);
+        assert!(after.dist.is_finite());
+
+        let (render_tris, stats) = app.render_tris(test_rect());
+        assert!(render_tris.is_empty());
+        assert_eq!(stats.non_finite, 0);
+    }
+
+    fn render_tri(z: [f32; 3], source: usize) -> RenderTri {
+        RenderTri { z, shade: [1.0; 3], specular: [0.0; 3], object: 0, source, pts: [[0.0; 2]; 3] }
+    }
+
+    #[test]
+    fn depth_sort_keeps_every_triangle_in_stable_order() {
+        let depths = [
+            [2.0, 2.0, 2.0],
+            [2.0, 2.0 + 1e-7, 2.0],
+            [5.0, 1.0, 3.0],
+            [2.0, 2.0, 2.0],
+            [2.0 + 1e-6, 2.0, 2.0 - 1e-6],
+            [0.5, 0.5, 0.5],
+            [5.0, 1.0, 3.0],
+        ];
+        let mut tris: Vec<RenderTri> = depths.into_iter().enumerate().map(|(i, z)| render_tri(z, i)).collect();
+        tris.sort_by_key(RenderTri::depth_key);
+        assert_eq!(tris.len(), depths.len());
+        assert_eq!(tris[0].max_z(), 5.0, "the farthest should come first");
+        for pair in tris.windows(2) {
+            assert!(pair[0].depth_key() <= pair[1].depth_key());
+            if pair[0].depth_key() == pair[1].depth_key() {
+                assert!(pair[0].source < pair[1].source, "equal keys should keep their order");
+            }
+        }
+    }
+
+    /// Serial vs. parallel timing of `render_tris` on a 500k triangle grid
+    /// filling the home view. Compare with and without `--features rayon`:
+    /// `cargo test --release [--features rayon] -- --ignored --nocapture render_tris_timing`
+    #[test]
+    #[ignore]
+    fn render_tris_timing() {
+        const CELLS: usize = 500;
+        let corner = |i: usize, j: usize| [i as f32 / CELLS as f32 * 2.0 - 1.0, j as f32 / CELLS as f32 * 2.0 - 1.0, 0.0];
+        let normal = [[0.0, 0.0, 1.0]; 3];
+        let mut triangles = Vec::with_capacity(CELLS * CELLS * 2);
+        for i in 0..CELLS {
+            for j in 0..CELLS {
+                triangles.push(Triangle::new([corner(i, j), corner(i + 1, j), corner(i + 1, j + 1)], normal, 0));
+                triangles.push(Triangle::new([corner(i, j), corner(i + 1, j + 1), corner(i, j + 1)], normal, 0));
+            }
+        }
+        let mut app = MyApp::new(None, None);
+        app.set_triangles(triangles);
+
+        let runs = 10;
+        let start = std::time::Instant::now();
+        let mut drawn = 0;
+        for _ in 0..runs {
+            drawn = app.render_tris(test_rect()).0.len();
+        }
+        let mode = if cfg!(feature = "rayon") { "parallel" } else { "serial" };
+        println!("{mode}: {drawn} of {} triangles in {:.1} ms", app.triangles.len(), start.elapsed().as_secs_f64() * 1000.0 / runs as f64);
+        assert_eq!(drawn, app.triangles.len());
+    }
+
+    #[test]
+    fn depth_heatmap_spans_range_at_any_scale() {
+        let mut app = MyApp::new(None, None);
+        app.color_mode = ColorMode::DepthHeatmap;
+        // Fitting the big cube backs the camera off past the default far plane
+        app.far = 1000.0;
+        for scale in [1.0, 100.0] {
+            let triangles = cube().iter().map(|tri| Triangle::new(tri.pos.map(|p| p.map(|v| v * scale)), tri.normal, 0)).collect();
+            app.set_triangles(triangles);
+            // A corner-on view, so the visible faces cover a spread of depths
+            app.orientation = orientation_from_yaw_pitch(0.6, 0.4);
+            app.fit_to_view(test_rect());
+
+            let (render_tris, _) = app.render_tris(test_rect());
+            let (near, far) = depth_range(&render_tris);
+            assert!(far - near > 0.1 * scale, "depths {near}..{far} at scale {scale}");
+            let colors: Vec<Color32> = render_tris.iter().flat_map(|tri| app.tri_colors(tri, (near, far))).collect();
+            assert!(colors.contains(&heatmap_color(0.0)), "no nearest color at scale {scale}");
+            assert!(colors.contains(&heatmap_color(1.0)), "no farthest color at scale {scale}");
+        }
+    }
+}
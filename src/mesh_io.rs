@@ -0,0 +1,217 @@
+//! Reading and writing mesh files.
+//!
+//! Files hold world-space triangles in the same units csgrs works in; no
+//! axis swaps or scaling happen on the way in or out. `MeshWatcher` notices
+//! when another program rewrites a loaded file.
+
+use std::{
+    collections::{HashMap, HashSet},
+    fs::File,
+    io::{self, BufReader, BufWriter, Write},
+    path::{Path, PathBuf},
+    sync::mpsc::{self, Receiver},
+    time::{Duration, Instant, SystemTime},
+};
+
+use eframe::egui;
+use notify::{Event, EventKind, RecommendedWatcher, RecursiveMode, Watcher};
+
+use crate::Triangle;
+
+/// Write `triangles` as a binary STL, with one flat normal per triangle.
+pub fn write_stl(path: &Path, triangles: &[Triangle]) -> io::Result<()> {
+    let mesh = triangles.iter().map(|tri| stl_io::Triangle {
+        normal: stl_io::Normal::new(tri.face.into()),
+        vertices: tri.pos.map(stl_io::Vertex::new),
+    });
+    let mut file = BufWriter::new(File::create(path)?);
+    stl_io::write_stl(&mut file, mesh)
+}
+
+/// Write `triangles` as a Wavefront OBJ with shared, indexed positions and
+/// per-corner normals. Corners are matched by exact bit pattern, which is
+/// how csgrs repeats them between neighboring faces.
+pub fn write_obj(path: &Path, triangles: &[Triangle]) -> io::Result<()> {
+    // OBJ indices are 1-based, and counted separately for positions and normals
+    fn index_of(p: [f32; 3], seen: &mut HashMap<[u32; 3], usize>, unique: &mut Vec<[f32; 3]>) -> usize {
+        *seen.entry(p.map(f32::to_bits)).or_insert_with(|| {
+            unique.push(p);
+            unique.len()
+        })
+    }
+    let (mut positions, mut normals) = (Vec::new(), Vec::new());
+    let (mut seen_positions, mut seen_normals) = (HashMap::new(), HashMap::new());
+    let faces: Vec<[(usize, usize); 3]> = triangles
+        .iter()
+        .map(|tri| {
+            [0, 1, 2].map(|k| {
+                (
+                    index_of(tri.pos[k], &mut seen_positions, &mut positions),
+                    index_of(tri.normal[k], &mut seen_normals, &mut normals),
+                )
+            })
+        })
+        .collect();
+
+    let mut out = BufWriter::new(File::create(path)?);
+    writeln!(out, "# {} vertices, {} triangles", positions.len(), faces.len())?;
+    for [x, y, z] in positions {
+        writeln!(out, "v {x} {y} {z}")?;
+    }
+    for [x, y, z] in normals {
+        writeln!(out, "vn {x} {y} {z}")?;
+    }
+    for [(v0, n0), (v1, n1), (v2, n2)] in faces {
+        writeln!(out, "f {v0}//{n0} {v1}//{n1} {v2}//{n2}")?;
+    }
+    out.flush()
+}
+
+/// Read an ASCII or binary STL. Each triangle gets its face normal at all
+/// three corners; the normals stored in the file are often zero or stale.
+pub fn read_stl(path: &Path) -> io::Result<Vec<Triangle>> {
+    let mesh = stl_io::read_stl(&mut BufReader::new(File::open(path)?))?;
+    let triangles = mesh
+        .faces
+        .iter()
+        .map(|face| {
+            let pos = face.vertices.map(|i| {
+                let v = mesh.vertices[i];
+                [v[0], v[1], v[2]]
+            });
+            let mut tri = Triangle::new(pos, [[0.0; 3]; 3], 0);
+            tri.normal = [tri.face.into(); 3];
+            tri
+        })
+        .collect();
+    Ok(triangles)
+}
+
+/// How long a watched file has to stay untouched before it counts as
+/// changed, so a tool that writes it in several steps causes one reload.
+const SETTLE_TIME: Duration = Duration::from_millis(300);
+
+/// Watches mesh files for changes made by other programs.
+///
+/// The folders holding the files are watched rather than the files
+/// themselves, since many tools save by writing a new file and renaming it
+/// over the old one.
+pub struct MeshWatcher {
+    watcher: RecommendedWatcher,
+    events: Receiver<notify::Result<Event>>,
+    /// The paths asked for in the last `set_files`.
+    requested: Vec<PathBuf>,
+    /// Watched files by their path in canonical folder: the path they were
+    /// asked for with, and their modification time when last reported.
+    files: HashMap<PathBuf, (PathBuf, Option<SystemTime>)>,
+    folders: HashSet<PathBuf>,
+    /// Files with changes that haven't settled yet, and when they last changed.
+    unsettled: HashMap<PathBuf, Instant>,
+    ctx: egui::Context,
+}
+
+impl MeshWatcher {
+    /// Start a watcher that repaints `ctx` whenever something happens, so
+    /// `poll` gets called.
+    pub fn new(ctx: egui::Context) -> notify::Result<Self> {
+        let (sender, events) = mpsc::channel();
+        let repaint = ctx.clone();
+        let watcher = notify::recommended_watcher(move |event| {
+            let _ = sender.send(event);
+            repaint.request_repaint();
+        })?;
+        Ok(Self {
+            watcher,
+            events,
+            requested: Vec::new(),
+            files: HashMap::new(),
+            folders: HashSet::new(),
+            unsettled: HashMap::new(),
+            ctx,
+        })
+    }
+
+    /// Watch exactly `paths`. Cheap when they're the same as last time.
+    pub fn set_files<'a>(&mut self, paths: impl IntoIterator<Item = &'a Path>) {
+        let mut requested: Vec<PathBuf> = paths.into_iter().map(Path::to_owned).collect();
+        requested.sort();
+        requested.dedup();
+        if requested == self.requested {
+            return;
+        }
+
+        let mut files = HashMap::new();
+        for path in &requested {
+            let Some(key) = watch_key(path) else { continue };
+            let modified = self.files.get(&key).map_or_else(|| modified(path), |&(_, modified)| modified);
+            files.insert(key, (path.clone(), modified));
+        }
+        let folders: HashSet<PathBuf> = files.keys().filter_map(|key| key.parent()).map(Path::to_owned).collect();
+        for folder in self.folders.difference(&folders) {
+            let _ = self.watcher.unwatch(folder);
+        }
+        for folder in folders.difference(&self.folders) {
+            if let Err(err) = self.watcher.watch(folder, RecursiveMode::NonRecursive) {
+                eprintln!("Couldn't watch {}: {err}", folder.display());
+            }
+        }
+        self.unsettled.retain(|key, _| files.contains_key(key));
+        (self.requested, self.files, self.folders) = (requested, files, folders);
+    }
+
+    /// The watched files whose contents changed since they were last
+    /// reported, as passed to `set_files`.
+    pub fn poll(&mut self) -> Vec<PathBuf> {
+        let now = Instant::now();
+        for event in self.events.try_iter().flatten() {
+            // Reading a file (including our own reloads) isn't a change
+            if matches!(event.kind, EventKind::Access(_)) {
+                continue;
+            }
+            for path in event.paths {
+                if self.files.contains_key(&path) {
+                    self.unsettled.insert(path, now);
+                }
+            }
+        }
+
+        let mut settled = Vec::new();
+        self.unsettled.retain(|key, &mut changed| {
+            let waiting = now - changed < SETTLE_TIME;
+            if !waiting {
+                settled.push(key.clone());
+            }
+            waiting
+        });
+        if !self.unsettled.is_empty() {
+            self.ctx.request_repaint_after(SETTLE_TIME);
+        }
+
+        // A change event doesn't always mean new contents; trust the modification time
+        settled
+            .into_iter()
+            .filter_map(|key| {
+                let (path, reported) = self.files.get_mut(&key)?;
+                let modified = modified(path);
+                (modified != *reported).then(|| {
+                    *reported = modified;
+                    path.clone()
+                })
+            })
+            .collect()
+    }
+}
+
+/// `path` with its folder made canonical, which is how change events name it.
+/// Only the folder has to exist; the file may be gone in the middle of a save.
+fn watch_key(path: &Path) -> Option<PathBuf> {
+    let folder = match path.parent() {
+        Some(folder) if !folder.as_os_str().is_empty() => folder,
+        _ => Path::new("."),
+    };
+    Some(folder.canonicalize().ok()?.join(path.file_name()?))
+}
+
+fn modified(path: &Path) -> Option<SystemTime> {
+    std::fs::metadata(path).and_then(|meta| meta.modified()).ok()
+}
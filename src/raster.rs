@@ -0,0 +1,223 @@
+//! A tiny software rasterizer with a depth buffer.
+//!
+//! The painter's algorithm can't draw interpenetrating triangles correctly
+//! (e.g. where the cube and sphere intersect), so this resolves visibility
+//! per pixel instead. The result is handed to egui as a single texture.
+
+use eframe::egui::{Color32, ColorImage};
+
+/// Relative slack in the depth test for lines, so edges lying exactly on a
+/// filled surface aren't hidden by the surface itself.
+const LINE_DEPTH_BIAS: f32 = 5e-3;
+
+/// Offscreen color + depth buffer.
+pub struct ZBuffer {
+    pub width: usize,
+    pub height: usize,
+    /// Per-pixel inverse camera-space depth (`1/z`). Larger is closer; 0 is empty.
+    depth: Vec<f32>,
+    color: Vec<Color32>,
+}
+
+impl ZBuffer {
+    pub fn new(width: usize, height: usize) -> Self {
+        Self {
+            width,
+            height,
+            depth: vec![0.0; width * height],
+            color: vec![Color32::TRANSPARENT; width * height],
+        }
+    }
+
+    /// Resize (if needed) and reset every pixel to `clear` at infinite depth.
+    pub fn clear(&mut self, width: usize, height: usize, clear: Color32) {
+        self.width = width;
+        self.height = height;
+        self.depth.clear();
+        self.depth.resize(width * height, 0.0);
+        self.color.clear();
+        self.color.resize(width * height, clear);
+    }
+
+    /// Paint a vertical gradient from `top` to `bottom` into the color buffer,
+    /// leaving depth alone.
+    pub fn fill_gradient(&mut self, top: Color32, bottom: Color32) {
+        for y in 0..self.height {
+            let t = (y as f32 + 0.5) / self.height as f32;
+            let color = top.lerp_to_gamma(bottom, t);
+            self.color[y * self.width..(y + 1) * self.width].fill(color);
+        }
+    }
+
+    /// Rasterize one triangle.
+    ///
+    /// `pts` are in pixel coordinates of this buffer, `z` are the camera-space
+    /// depths of the corners. Depth is interpolated as `1/z`, which is linear
+    /// in screen space, so the test is perspective-correct. Corner colors are
+    /// blended linearly in screen space (Gouraud).
+    pub fn fill_triangle(&mut self, pts: [[f32; 2]; 3], z: [f32; 3], colors: [Color32; 3]) {
+        self.rasterize(pts, z, Fill::Color(colors));
+    }
+
+    /// Like [`ZBuffer::fill_triangle`], but only writes depth, so the triangle
+    /// hides what's behind it without being drawn itself.
+    pub fn fill_depth(&mut self, pts: [[f32; 2]; 3], z: [f32; 3]) {
+        self.rasterize(pts, z, Fill::Depth);
+    }
+
+    /// Like [`ZBuffer::fill_triangle`], but blends the (premultiplied) colors
+    /// over what's already there and leaves depth alone. Translucent
+    /// triangles drawn this way after the opaque ones, back to front, show
+    /// what's behind them.
+    pub fn blend_triangle(&mut self, pts: [[f32; 2]; 3], z: [f32; 3], colors: [Color32; 3]) {
+        self.rasterize(pts, z, Fill::Blend(colors));
+    }
+
+    fn rasterize(&mut self, pts: [[f32; 2]; 3], z: [f32; 3], fill: Fill) {
+        let [a, b, c] = pts;
+        let area = edge(a, b, c);
+        if area.abs() < f32::EPSILON {
+            return; // degenerate
+        }
+        let inv_z = [1.0 / z[0], 1.0 / z[1], 1.0 / z[2]];
+
+        // Pixel bounding box, clamped to the buffer
+        let min_x = a[0].min(b[0]).min(c[0]).floor().max(0.0) as usize;
+        let min_y = a[1].min(b[1]).min(c[1]).floor().max(0.0) as usize;
+        let max_x = (a[0].max(b[0]).max(c[0]).ceil() as usize).min(self.width);
+        let max_y = (a[1].max(b[1]).max(c[1]).ceil() as usize).min(self.height);
+
+        for y in min_y..max_y {
+            for x in min_x..max_x {
+                // Sample at the pixel center
+                let p = [x as f32 + 0.5, y as f32 + 0.5];
+                let w0 = edge(b, c, p) / area;
+                let w1 = edge(c, a, p) / area;
+                let w2 = edge(a, b, p) / area;
+                if w0 < 0.0 || w1 < 0.0 || w2 < 0.0 {
+                    continue;
+                }
+
+                let d = w0 * inv_z[0] + w1 * inv_z[1] + w2 * inv_z[2];
+                let idx = y * self.width + x;
+                if d > self.depth[idx] {
+                    match fill {
+                        Fill::Depth => self.depth[idx] = d,
+                        Fill::Color(colors) => {
+                            self.depth[idx] = d;
+                            self.color[idx] = blend3(colors, [w0, w1, w2]);
+                        }
+                        Fill::Blend(colors) => self.color[idx] = over(blend3(colors, [w0, w1, w2]), self.color[idx]),
+                    }
+                }
+            }
+        }
+    }
+
+    /// Draw a `size` pixels wide line that only shows where it passes the
+    /// depth test, without writing depth. Used for hidden-line rendering.
+    ///
+    /// Same coordinate conventions as [`ZBuffer::fill_triangle`].
+    pub fn draw_line(&mut self, a: [f32; 2], b: [f32; 2], z: [f32; 2], size: usize, color: Color32) {
+        let (dx, dy) = (b[0] - a[0], b[1] - a[1]);
+        let steps = dx.abs().max(dy.abs()).ceil().max(1.0) as usize;
+        let inv_z = [1.0 / z[0], 1.0 / z[1]];
+        // Stamp a square of pixels, centered on the line
+        let offset = (size.max(1) - 1) as f32 * 0.5;
+
+        for i in 0..=steps {
+            let t = i as f32 / steps as f32;
+            let d = inv_z[0] + (inv_z[1] - inv_z[0]) * t;
+            for sy in 0..size.max(1) {
+                for sx in 0..size.max(1) {
+                    let x = a[0] + dx * t - offset + sx as f32;
+                    let y = a[1] + dy * t - offset + sy as f32;
+                    if x < 0.0 || y < 0.0 {
+                        continue;
+                    }
+                    let (x, y) = (x as usize, y as usize);
+                    if x >= self.width || y >= self.height {
+                        continue;
+                    }
+
+                    let idx = y * self.width + x;
+                    if d >= self.depth[idx] * (1.0 - LINE_DEPTH_BIAS) {
+                        self.color[idx] = color;
+                    }
+                }
+            }
+        }
+    }
+
+    /// Copy the color buffer into an egui image, averaging each `samples` by
+    /// `samples` block of pixels into one. Drawing at `samples` times the
+    /// image size and scaling down like this smooths jagged edges.
+    pub fn to_image(&self, samples: usize) -> ColorImage {
+        let samples = samples.max(1);
+        if samples == 1 {
+            return ColorImage {
+                size: [self.width, self.height],
+                pixels: self.color.clone(),
+            };
+        }
+        let (width, height) = (self.width / samples, self.height / samples);
+        let count = (samples * samples) as u32;
+        let mut pixels = Vec::with_capacity(width * height);
+        for y in 0..height {
+            for x in 0..width {
+                // Colors are premultiplied, so a plain average is the right blend
+                let mut sum = [0u32; 4];
+                for sy in 0..samples {
+                    let row = (y * samples + sy) * self.width + x * samples;
+                    for c in &self.color[row..row + samples] {
+                        for (s, v) in sum.iter_mut().zip(c.to_array()) {
+                            *s += v as u32;
+                        }
+                    }
+                }
+                let [r, g, b, a] = sum.map(|s| ((s + count / 2) / count) as u8);
+                pixels.push(Color32::from_rgba_premultiplied(r, g, b, a));
+            }
+        }
+        ColorImage { size: [width, height], pixels }
+    }
+}
+
+/// What `ZBuffer::rasterize` writes where a triangle passes the depth test.
+#[derive(Clone, Copy)]
+enum Fill {
+    Depth,
+    Color([Color32; 3]),
+    Blend([Color32; 3]),
+}
+
+/// Premultiplied `top` composited over `bottom`.
+fn over(top: Color32, bottom: Color32) -> Color32 {
+    let keep = 255 - top.a() as u16;
+    let ch = |t: u8, b: u8| (t as u16 + (b as u16 * keep + 127) / 255).min(255) as u8;
+    Color32::from_rgba_premultiplied(
+        ch(top.r(), bottom.r()),
+        ch(top.g(), bottom.g()),
+        ch(top.b(), bottom.b()),
+        ch(top.a(), bottom.a()),
+    )
+}
+
+/// Weighted sum of three colors; the weights should add up to 1.
+fn blend3(c: [Color32; 3], w: [f32; 3]) -> Color32 {
+    if c[0] == c[1] && c[1] == c[2] {
+        return c[0]; // flat shaded, skip the math
+    }
+    let ch = |f: fn(&Color32) -> u8| {
+        (f(&c[0]) as f32 * w[0] + f(&c[1]) as f32 * w[1] + f(&c[2]) as f32 * w[2])
+            .round()
+            .clamp(0.0, 255.0) as u8
+    };
+    Color32::from_rgba_premultiplied(ch(Color32::r), ch(Color32::g), ch(Color32::b), ch(Color32::a))
+}
+
+/// Twice the signed area of triangle `(a, b, p)`.
+/// Dividing by the full triangle's value gives a barycentric weight.
+fn edge(a: [f32; 2], b: [f32; 2], p: [f32; 2]) -> f32 {
+    (b[0] - a[0]) * (p[1] - a[1]) - (b[1] - a[1]) * (p[0] - a[0])
+}
@@ -0,0 +1,387 @@
+//! The scene: a list of objects, each built from csgrs primitives and placed
+//! in the world with its own transform.
+//!
+//! Objects keep their triangles in object space and only rebuild them when
+//! their recipe changes; `MyApp` flattens the visible ones into world space.
+
+use std::{
+    path::PathBuf,
+    sync::{mpsc::Sender, Arc},
+};
+
+use eframe::egui::Color32;
+use nalgebra::{Matrix4, Rotation3, Vector3};
+use serde::{Deserialize, Serialize};
+
+use crate::Triangle;
+
+#[allow(clippy::upper_case_acronyms)]
+pub type CSG = csgrs::csg::CSG<()>;
+
+/// A csgrs primitive that can be an operand of the boolean operation.
+#[derive(Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Primitive {
+    Cube,
+    Sphere,
+    Cylinder,
+}
+
+impl Primitive {
+    pub const ALL: [Primitive; 3] = [Primitive::Cube, Primitive::Sphere, Primitive::Cylinder];
+
+    pub fn label(self) -> &'static str {
+        match self {
+            Primitive::Cube => "Cube",
+            Primitive::Sphere => "Sphere",
+            Primitive::Cylinder => "Cylinder",
+        }
+    }
+}
+
+/// How the two operands A and B are combined.
+#[derive(Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum BooleanOp {
+    Union,
+    Intersection,
+    /// A minus B.
+    Difference,
+    /// B minus A.
+    ReverseDifference,
+    /// Everything inside exactly one of the two.
+    Xor,
+}
+
+impl BooleanOp {
+    pub const ALL: [BooleanOp; 5] = [
+        BooleanOp::Union,
+        BooleanOp::Intersection,
+        BooleanOp::Difference,
+        BooleanOp::ReverseDifference,
+        BooleanOp::Xor,
+    ];
+
+    pub fn label(self) -> &'static str {
+        match self {
+            BooleanOp::Union => "Union",
+            BooleanOp::Intersection => "Intersection",
+            BooleanOp::Difference => "A − B",
+            BooleanOp::ReverseDifference => "B − A",
+            BooleanOp::Xor => "Xor",
+        }
+    }
+
+    pub fn apply(self, a: &CSG, b: &CSG) -> CSG {
+        match self {
+            BooleanOp::Union => a.union(b),
+            BooleanOp::Intersection => a.intersection(b),
+            BooleanOp::Difference => a.difference(b),
+            BooleanOp::ReverseDifference => b.difference(a),
+            // csgrs has no xor; the two differences don't overlap, so union them
+            BooleanOp::Xor => a.difference(b).union(&b.difference(a)),
+        }
+    }
+}
+
+/// Primitive dimensions. The cube spans `[0, cube_size]` on each axis, the
+/// sphere is centered on the origin and the cylinder stands on Z, centered.
+#[derive(Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct PrimitiveParams {
+    pub cube_size: f64,
+    pub sphere_radius: f64,
+    pub sphere_slices: usize,
+    pub sphere_stacks: usize,
+    pub cylinder_radius: f64,
+    pub cylinder_height: f64,
+    pub cylinder_segments: usize,
+    /// Times the boolean result is subdivided; each level splits every
+    /// triangle into four.
+    pub subdivisions: u32,
+}
+
+impl Default for PrimitiveParams {
+    fn default() -> Self {
+        Self {
+            cube_size: 1.0,
+            sphere_radius: 1.0,
+            sphere_slices: 16,
+            sphere_stacks: 8,
+            cylinder_radius: 0.5,
+            cylinder_height: 2.0,
+            cylinder_segments: 24,
+            subdivisions: 0,
+        }
+    }
+}
+
+impl PrimitiveParams {
+    /// These parameters with the sphere's slices and stacks halved `level`
+    /// times, for when it's too far away for the detail to show.
+    pub fn coarsened(&self, level: u32) -> Self {
+        Self {
+            // Never go below a recognizable ball, or above the full detail
+            sphere_slices: (self.sphere_slices >> level).max(self.sphere_slices.min(6)),
+            sphere_stacks: (self.sphere_stacks >> level).max(self.sphere_stacks.min(3)),
+            ..*self
+        }
+    }
+
+    pub fn build(&self, primitive: Primitive) -> CSG {
+        match primitive {
+            Primitive::Cube => CSG::cube(self.cube_size, self.cube_size, self.cube_size, None),
+            Primitive::Sphere => CSG::sphere(self.sphere_radius, self.sphere_slices, self.sphere_stacks, None),
+            Primitive::Cylinder => CSG::cylinder(self.cylinder_radius, self.cylinder_height, self.cylinder_segments, None)
+                .translate(Vector3::new(0.0, 0.0, -self.cylinder_height * 0.5)),
+        }
+    }
+}
+
+/// Triangles loaded from a mesh file, in the file's coordinates.
+pub struct ImportedMesh {
+    pub path: PathBuf,
+    pub triangles: Vec<Triangle>,
+}
+
+/// Meshes are only equal to themselves: scene snapshots are compared every
+/// frame, and comparing triangle lists would be far too slow.
+impl PartialEq for ImportedMesh {
+    fn eq(&self, other: &Self) -> bool {
+        std::ptr::eq(self, other)
+    }
+}
+
+/// Everything the user can edit on a scene object, without the geometry
+/// built from it. Small enough to snapshot freely for undo, and what scene
+/// files store.
+#[derive(Clone, PartialEq, Serialize, Deserialize)]
+pub struct ObjectState {
+    pub name: String,
+    pub color: Color32,
+    pub visible: bool,
+    pub a: Primitive,
+    pub op: BooleanOp,
+    pub b: Option<Primitive>,
+    pub params: PrimitiveParams,
+    /// Saved as the file path, and read back from it on load.
+    #[serde(with = "mesh_path")]
+    pub mesh: Option<Arc<ImportedMesh>>,
+    pub position: Vector3<f32>,
+    pub rotation: Vector3<f32>,
+    pub scale: Vector3<f32>,
+}
+
+/// One independently placed, colored and toggled part of the scene.
+pub struct SceneObject {
+    pub name: String,
+    pub color: Color32,
+    pub visible: bool,
+    /// Geometry recipe: `a` alone, or `a op b` when there's a `b`.
+    pub a: Primitive,
+    pub op: BooleanOp,
+    pub b: Option<Primitive>,
+    pub params: PrimitiveParams,
+    /// An imported mesh, which replaces the primitive recipe when set.
+    pub mesh: Option<Arc<ImportedMesh>>,
+    /// Placement: scale, then rotate about X, Y and Z (degrees), then move
+    /// to `position`. Call `update_transform` after changing these.
+    pub position: Vector3<f32>,
+    pub rotation: Vector3<f32>,
+    pub scale: Vector3<f32>,
+    /// Object-to-world transform, composed from the placement fields.
+    pub transform: Matrix4<f32>,
+    /// The built model and its triangles, in object space. Imported meshes
+    /// only fill in `triangles`.
+    pub csg: CSG,
+    pub triangles: Vec<Triangle>,
+    /// Set when the recipe changed and `csg` is stale.
+    pub dirty: bool,
+    /// The ticket of the build running on a worker thread, if any. Its
+    /// result is used when it arrives even if `dirty` was set again since;
+    /// it's still newer than what's shown.
+    pub pending: Option<u64>,
+}
+
+/// Geometry built on a worker thread, for the object whose `pending`
+/// build is `ticket`.
+pub struct BuiltGeometry {
+    pub ticket: u64,
+    pub csg: CSG,
+    pub triangles: Vec<Triangle>,
+}
+
+impl SceneObject {
+    pub fn new(name: impl Into<String>, color: Color32, a: Primitive, op: BooleanOp, b: Option<Primitive>) -> Self {
+        Self {
+            name: name.into(),
+            color,
+            visible: true,
+            a,
+            op,
+            b,
+            params: PrimitiveParams::default(),
+            mesh: None,
+            position: Vector3::zeros(),
+            rotation: Vector3::zeros(),
+            scale: Vector3::repeat(1.0),
+            transform: Matrix4::identity(),
+            csg: CSG::new(),
+            triangles: Vec::new(),
+            dirty: true,
+            pending: None,
+        }
+    }
+
+    /// Rebuild `csg` and `triangles` from the recipe, and clear `dirty`.
+    ///
+    /// Booleans can take a while, so primitive recipes are built on a worker
+    /// thread that sends the result to `results`, tagged with `ticket`. The
+    /// old geometry stays until it's passed to `finish_build`. Imported
+    /// meshes are copied right away. Spheres are built at level of detail
+    /// `lod` (see `PrimitiveParams::coarsened`).
+    pub fn start_build(&mut self, ticket: u64, results: &Sender<BuiltGeometry>, lod: u32) {
+        self.dirty = false;
+        if let Some(mesh) = &self.mesh {
+            self.pending = None;
+            self.csg = CSG::new();
+            self.triangles = mesh.triangles.clone();
+            return;
+        }
+        self.pending = Some(ticket);
+        let (a, op, b, params) = (self.a, self.op, self.b, self.params.coarsened(lod));
+        let results = results.clone();
+        std::thread::spawn(move || {
+            let a = params.build(a);
+            let mut csg = match b {
+                Some(b) => op.apply(&a, &params.build(b)),
+                None => a,
+            };
+            if params.subdivisions > 0 {
+                csg = csg.subdivide_triangles(params.subdivisions);
+            }
+            let triangles = csg_triangles(&csg);
+            // The app may have closed in the meantime
+            let _ = results.send(BuiltGeometry { ticket, csg, triangles });
+        });
+    }
+
+    /// Take the geometry from the build started with `built.ticket`.
+    pub fn finish_build(&mut self, built: BuiltGeometry) {
+        self.pending = None;
+        self.csg = built.csg;
+        self.triangles = built.triangles;
+    }
+
+    /// Whether the recipe builds `primitive`; never for imported meshes.
+    pub fn uses(&self, primitive: Primitive) -> bool {
+        self.mesh.is_none() && (self.a == primitive || self.b == Some(primitive))
+    }
+
+    pub fn from_mesh(name: impl Into<String>, color: Color32, mesh: ImportedMesh) -> Self {
+        let mut object = Self::new(name, color, Primitive::Cube, BooleanOp::Union, None);
+        object.mesh = Some(Arc::new(mesh));
+        object
+    }
+
+    pub fn state(&self) -> ObjectState {
+        ObjectState {
+            name: self.name.clone(),
+            color: self.color,
+            visible: self.visible,
+            a: self.a,
+            op: self.op,
+            b: self.b,
+            params: self.params,
+            mesh: self.mesh.clone(),
+            position: self.position,
+            rotation: self.rotation,
+            scale: self.scale,
+        }
+    }
+
+    /// Go back to `state`. The built geometry is kept if the recipe is the same.
+    pub fn restore(&mut self, state: ObjectState) {
+        let recipe_changed =
+            (self.a, self.op, self.b, self.params, &self.mesh) != (state.a, state.op, state.b, state.params, &state.mesh);
+        self.dirty |= recipe_changed;
+        self.name = state.name;
+        self.color = state.color;
+        self.visible = state.visible;
+        self.a = state.a;
+        self.op = state.op;
+        self.b = state.b;
+        self.params = state.params;
+        self.mesh = state.mesh;
+        self.position = state.position;
+        self.rotation = state.rotation;
+        self.scale = state.scale;
+        self.update_transform();
+    }
+
+    /// Recompute `transform` from `position`, `rotation` and `scale`.
+    pub fn update_transform(&mut self) {
+        let r = self.rotation.map(f32::to_radians);
+        let rotation = Rotation3::from_euler_angles(r.x, r.y, r.z);
+        self.transform = Matrix4::new_translation(&self.position)
+            * rotation.to_homogeneous()
+            * Matrix4::new_nonuniform_scaling(&self.scale);
+    }
+
+    /// This object's triangles moved into world space and then by `offset`,
+    /// tagged with `index`, its position in the scene.
+    pub fn world_triangles(&self, index: usize, offset: Vector3<f32>) -> impl Iterator<Item = Triangle> + '_ {
+        let transform = Matrix4::new_translation(&offset) * self.transform;
+        // Normals go through the inverse transpose, so non-uniform scales keep them perpendicular
+        let normal_matrix = self
+            .transform
+            .fixed_view::<3, 3>(0, 0)
+            .try_inverse()
+            .unwrap_or_else(nalgebra::Matrix3::identity)
+            .transpose();
+        self.triangles.iter().map(move |tri| {
+            Triangle::new(
+                tri.pos.map(|p| transform.transform_point(&p.into()).into()),
+                tri.normal.map(|n| {
+                    (normal_matrix * Vector3::from(n)).try_normalize(f32::EPSILON).unwrap_or_else(Vector3::zeros).into()
+                }),
+                index,
+            )
+        })
+    }
+}
+
+/// Triangulate a CSG into the viewer's `f32` triangles, all tagged as object 0.
+pub fn csg_triangles(csg: &CSG) -> Vec<Triangle> {
+    let mut triangles = Vec::new();
+    for poly in &csg.polygons {
+        // Triangulate each polygon (most are already triangles after `subdivide_triangles`)
+        for tri in poly.triangulate() {
+            triangles.push(Triangle::new(
+                tri.each_ref().map(|v| [v.pos.x as f32, v.pos.y as f32, v.pos.z as f32]),
+                tri.each_ref().map(|v| [v.normal.x as f32, v.normal.y as f32, v.normal.z as f32]),
+                0,
+            ));
+        }
+    }
+    triangles
+}
+
+/// Serde adapter that stores an imported mesh as the path it came from.
+mod mesh_path {
+    use std::{path::PathBuf, sync::Arc};
+
+    use serde::{de::Error, Deserialize, Deserializer, Serialize, Serializer};
+
+    use super::ImportedMesh;
+
+    pub fn serialize<S: Serializer>(mesh: &Option<Arc<ImportedMesh>>, serializer: S) -> Result<S::Ok, S::Error> {
+        mesh.as_ref().map(|mesh| &mesh.path).serialize(serializer)
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Option<Arc<ImportedMesh>>, D::Error> {
+        let Some(path) = Option::<PathBuf>::deserialize(deserializer)? else {
+            return Ok(None);
+        };
+        let triangles = crate::mesh_io::read_stl(&path)
+            .map_err(|err| D::Error::custom(format!("couldn't read {}: {err}", path.display())))?;
+        Ok(Some(Arc::new(ImportedMesh { path, triangles })))
+    }
+}